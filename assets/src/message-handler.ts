@@ -11,6 +11,7 @@ import {
     ConversationListMessage,
     MessageResponse,
     ErrorMessage,
+    SettingsInvalidMessage,
     SettingsMessage,
     HistoryMessage,
     MessagesResponse,
@@ -71,6 +72,10 @@ export class MessageHandler {
                 this.handleError(message as ErrorMessage);
                 break;
                 
+            case 'settings_invalid':
+                this.handleSettingsInvalid(message as SettingsInvalidMessage);
+                break;
+                
             case 'success':
                 // Generic success, no specific handling needed
                 break;
@@ -260,6 +265,19 @@ export class MessageHandler {
         this.uiManager.showError(message.error_code, message.message);
     }
     
+    /**
+     * Handle settings_invalid message
+     * @param {SettingsInvalidMessage} message - The rejected settings, one error per field
+     */
+    handleSettingsInvalid(message: SettingsInvalidMessage): void {
+        console.error('Settings rejected:', message.errors);
+        
+        const details = message.errors
+            .map(error => `${error.field}: ${error.message}`)
+            .join('; ');
+        this.uiManager.showError('Invalid settings', details);
+    }
+    
     /**
      * Request conversation history
      * @param {string} conversationId - The conversation ID
@@ -90,6 +90,15 @@ export interface ServerMessage {
   error_code?: string;
 }
 
+export interface WelcomeMessage extends ServerMessage {
+  type: 'welcome';
+  protocol_version: number;
+  server_version: string;
+  capabilities: string[];
+  session_id: string;
+  resumed: boolean;
+}
+
 export interface ConversationCreatedMessage extends ServerMessage {
   type: 'conversation_created';
   conversation_id: string;
@@ -109,6 +118,17 @@ export interface ErrorMessage extends ServerMessage {
   error_code: string;
 }
 
+export interface FieldError {
+  field: string;
+  message: string;
+}
+
+export interface SettingsInvalidMessage extends ServerMessage {
+  type: 'settings_invalid';
+  conversation_id: string;
+  errors: FieldError[];
+}
+
 export interface ConversationListMessage extends ServerMessage {
   type: 'conversation_list';
   conversations: Record<string, Conversation>;
@@ -3,7 +3,10 @@
  * Handles WebSocket communication with the chat-interface backend
  */
 
-import { WebSocketMessage, ServerMessage } from './types';
+import { WebSocketMessage, ServerMessage, WelcomeMessage } from './types';
+
+// How often to ping the server so the connection isn't closed as idle
+const HEARTBEAT_INTERVAL_MS = 60000;
 
 export class WebSocketManager {
     private socket: WebSocket | null;
@@ -11,6 +14,9 @@ export class WebSocketManager {
     private reconnectAttempts: number;
     private maxReconnectAttempts: number;
     private reconnectTimeout: number | null;
+    // Token from the welcome frame, sent back on reconnect to resume the session
+    private sessionId: string | null;
+    private heartbeatInterval: number | null;
     
     // Event callbacks
     onOpen: ((event: Event) => void) | null;
@@ -24,6 +30,8 @@ export class WebSocketManager {
         this.reconnectAttempts = 0;
         this.maxReconnectAttempts = 5;
         this.reconnectTimeout = null;
+        this.sessionId = null;
+        this.heartbeatInterval = null;
         
         // Event callbacks
         this.onOpen = null;
@@ -41,7 +49,10 @@ export class WebSocketManager {
         }
         
         const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
-        const wsUrl = `${protocol}//${window.location.host}/ws`;
+        // Set by the server when the app is served under a base path
+        const basePath = (window as any).CHAT_BASE_PATH ?? '';
+        const session = this.sessionId ? `?session=${encodeURIComponent(this.sessionId)}` : '';
+        const wsUrl = `${protocol}//${window.location.host}${basePath}/ws${session}`;
         
         console.log(`Connecting to WebSocket: ${wsUrl}`);
         
@@ -51,6 +62,7 @@ export class WebSocketManager {
             console.log('WebSocket connection established');
             this.connected = true;
             this.reconnectAttempts = 0;
+            this.startHeartbeat();
             
             if (this.onOpen) {
                 this.onOpen(event);
@@ -66,6 +78,15 @@ export class WebSocketManager {
                 return;
             }
             
+            if (message.type === 'welcome') {
+                this.sessionId = (message as WelcomeMessage).session_id;
+            }
+            
+            // Heartbeat replies are not for the app
+            if (message.type === 'pong') {
+                return;
+            }
+            
             if (this.onMessage) {
                 this.onMessage(message);
             }
@@ -74,6 +95,7 @@ export class WebSocketManager {
         this.socket.onclose = (event: CloseEvent) => {
             console.log('WebSocket connection closed:', event.code, event.reason);
             this.connected = false;
+            this.stopHeartbeat();
             
             if (this.onClose) {
                 this.onClose(event);
@@ -104,6 +126,7 @@ export class WebSocketManager {
         }
         
         this.connected = false;
+        this.stopHeartbeat();
         
         // Clear any pending reconnect
         if (this.reconnectTimeout) {
@@ -112,6 +135,26 @@ export class WebSocketManager {
         }
     }
     
+    /**
+     * Ping the server periodically while connected
+     */
+    startHeartbeat(): void {
+        this.stopHeartbeat();
+        this.heartbeatInterval = window.setInterval(() => {
+            this.sendMessage({ action: 'ping' });
+        }, HEARTBEAT_INTERVAL_MS);
+    }
+    
+    /**
+     * Stop pinging the server
+     */
+    stopHeartbeat(): void {
+        if (this.heartbeatInterval) {
+            clearInterval(this.heartbeatInterval);
+            this.heartbeatInterval = null;
+        }
+    }
+    
     /**
      * Schedule a reconnection attempt
      */
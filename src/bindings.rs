@@ -1774,6 +1774,143 @@ pub mod ntwk {
             }
         }
         #[allow(dead_code, async_fn_in_trait, unused_imports, clippy::all)]
+        pub mod filesystem {
+            #[used]
+            #[doc(hidden)]
+            static __FORCE_SECTION_REF: fn() = super::super::super::__link_custom_section_describing_imports;
+            use super::super::super::_rt;
+            #[allow(unused_unsafe, clippy::all)]
+            pub fn read_file(path: &str) -> Result<_rt::Vec<u8>, _rt::String> {
+                unsafe {
+                    #[cfg_attr(target_pointer_width = "64", repr(align(8)))]
+                    #[cfg_attr(target_pointer_width = "32", repr(align(4)))]
+                    struct RetArea(
+                        [::core::mem::MaybeUninit<
+                            u8,
+                        >; 3 * ::core::mem::size_of::<*const u8>()],
+                    );
+                    let mut ret_area = RetArea(
+                        [::core::mem::MaybeUninit::uninit(); 3
+                            * ::core::mem::size_of::<*const u8>()],
+                    );
+                    let vec0 = path;
+                    let ptr0 = vec0.as_ptr().cast::<u8>();
+                    let len0 = vec0.len();
+                    let ptr1 = ret_area.0.as_mut_ptr().cast::<u8>();
+                    #[cfg(target_arch = "wasm32")]
+                    #[link(wasm_import_module = "ntwk:theater/filesystem")]
+                    unsafe extern "C" {
+                        #[link_name = "read-file"]
+                        fn wit_import2(_: *mut u8, _: usize, _: *mut u8);
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    unsafe extern "C" fn wit_import2(_: *mut u8, _: usize, _: *mut u8) {
+                        unreachable!()
+                    }
+                    unsafe { wit_import2(ptr0.cast_mut(), len0, ptr1) };
+                    let l2 = i32::from(*ptr1.add(0).cast::<u8>());
+                    let result9 = match l2 {
+                        0 => {
+                            let e = {
+                                let l3 = *ptr1
+                                    .add(::core::mem::size_of::<*const u8>())
+                                    .cast::<*mut u8>();
+                                let l4 = *ptr1
+                                    .add(2 * ::core::mem::size_of::<*const u8>())
+                                    .cast::<usize>();
+                                let len5 = l4;
+                                _rt::Vec::from_raw_parts(l3.cast(), len5, len5)
+                            };
+                            Ok(e)
+                        }
+                        1 => {
+                            let e = {
+                                let l6 = *ptr1
+                                    .add(::core::mem::size_of::<*const u8>())
+                                    .cast::<*mut u8>();
+                                let l7 = *ptr1
+                                    .add(2 * ::core::mem::size_of::<*const u8>())
+                                    .cast::<usize>();
+                                let len8 = l7;
+                                let bytes8 = _rt::Vec::from_raw_parts(
+                                    l6.cast(),
+                                    len8,
+                                    len8,
+                                );
+                                _rt::string_lift(bytes8)
+                            };
+                            Err(e)
+                        }
+                        _ => _rt::invalid_enum_discriminant(),
+                    };
+                    result9
+                }
+            }
+            #[allow(unused_unsafe, clippy::all)]
+            pub fn path_exists(path: &str) -> Result<bool, _rt::String> {
+                unsafe {
+                    #[cfg_attr(target_pointer_width = "64", repr(align(8)))]
+                    #[cfg_attr(target_pointer_width = "32", repr(align(4)))]
+                    struct RetArea(
+                        [::core::mem::MaybeUninit<
+                            u8,
+                        >; 3 * ::core::mem::size_of::<*const u8>()],
+                    );
+                    let mut ret_area = RetArea(
+                        [::core::mem::MaybeUninit::uninit(); 3
+                            * ::core::mem::size_of::<*const u8>()],
+                    );
+                    let vec0 = path;
+                    let ptr0 = vec0.as_ptr().cast::<u8>();
+                    let len0 = vec0.len();
+                    let ptr1 = ret_area.0.as_mut_ptr().cast::<u8>();
+                    #[cfg(target_arch = "wasm32")]
+                    #[link(wasm_import_module = "ntwk:theater/filesystem")]
+                    unsafe extern "C" {
+                        #[link_name = "path-exists"]
+                        fn wit_import2(_: *mut u8, _: usize, _: *mut u8);
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    unsafe extern "C" fn wit_import2(_: *mut u8, _: usize, _: *mut u8) {
+                        unreachable!()
+                    }
+                    unsafe { wit_import2(ptr0.cast_mut(), len0, ptr1) };
+                    let l2 = i32::from(*ptr1.add(0).cast::<u8>());
+                    let result9 = match l2 {
+                        0 => {
+                            let e = {
+                                let l3 = i32::from(
+                                    *ptr1.add(::core::mem::size_of::<*const u8>()).cast::<u8>(),
+                                );
+                                _rt::bool_lift(l3 as u8)
+                            };
+                            Ok(e)
+                        }
+                        1 => {
+                            let e = {
+                                let l6 = *ptr1
+                                    .add(::core::mem::size_of::<*const u8>())
+                                    .cast::<*mut u8>();
+                                let l7 = *ptr1
+                                    .add(2 * ::core::mem::size_of::<*const u8>())
+                                    .cast::<usize>();
+                                let len8 = l7;
+                                let bytes8 = _rt::Vec::from_raw_parts(
+                                    l6.cast(),
+                                    len8,
+                                    len8,
+                                );
+                                _rt::string_lift(bytes8)
+                            };
+                            Err(e)
+                        }
+                        _ => _rt::invalid_enum_discriminant(),
+                    };
+                    result9
+                }
+            }
+        }
+        #[allow(dead_code, async_fn_in_trait, unused_imports, clippy::all)]
         pub mod http_client {
             #[used]
             #[doc(hidden)]
@@ -2008,113 +2145,370 @@ pub mod ntwk {
                 }
             }
         }
-        /// # Supervisor Interface
-        ///
-        /// Defines the interface for actor supervision in the Theater system. This allows parent actors
-        /// to manage the lifecycle and monitor the state of their child actors.
-        ///
-        /// ## Purpose
-        ///
-        /// The supervisor interface enables an important part of the Theater architecture: the supervision
-        /// tree. Similar to Erlang's supervision system, this allows actors to monitor and manage other
-        /// actors, creating a hierarchical structure that enhances fault tolerance and system management.
-        ///
-        /// Through this interface, parent actors can:
-        /// - Spawn new child actors
-        /// - Monitor child actor status and state
-        /// - Restart failed child actors
-        /// - Access child actor event history
-        ///
-        /// ## Example
-        ///
-        /// In a typical Theater actor, supervision capabilities would be used like this:
-        ///
-        /// ```rust
-        /// use ntwk::theater::supervisor;
-        ///
-        /// // Spawn a new child actor from a manifest
-        /// let child_id = supervisor::spawn("child_manifest.toml", None)?;
-        ///
-        /// // Later, restart the child if needed
-        /// supervisor::restart_child(child_id)?;
-        ///
-        /// // Get the current state of the child
-        /// let state = supervisor::get_child_state(child_id)?;
-        /// ```
-        ///
-        /// ## Security
-        ///
-        /// The supervisor interface has significant privileges, as it can control other actors and
-        /// access their state. The Theater runtime ensures that an actor can only supervise its
-        /// direct children, enforcing proper hierarchy boundaries.
-        ///
-        /// ## Implementation Notes
-        ///
-        /// The interface uses string-based actor identifiers and returns results that can contain
-        /// errors as strings. This allows for human-readable error messages and flexible actor
-        /// identification across the system.
         #[allow(dead_code, async_fn_in_trait, unused_imports, clippy::all)]
-        pub mod supervisor {
+        pub mod process {
             #[used]
             #[doc(hidden)]
             static __FORCE_SECTION_REF: fn() = super::super::super::__link_custom_section_describing_imports;
             use super::super::super::_rt;
-            pub type ChainEvent = super::super::super::ntwk::theater::types::ChainEvent;
+            #[repr(u8)]
+            #[derive(Clone, Copy, Eq, Ord, PartialEq, PartialOrd)]
+            pub enum OutputMode {
+                Raw,
+                LineByLine,
+                Json,
+                Chunked,
+            }
+            impl ::core::fmt::Debug for OutputMode {
+                fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                    match self {
+                        OutputMode::Raw => f.debug_tuple("OutputMode::Raw").finish(),
+                        OutputMode::LineByLine => {
+                            f.debug_tuple("OutputMode::LineByLine").finish()
+                        }
+                        OutputMode::Json => f.debug_tuple("OutputMode::Json").finish(),
+                        OutputMode::Chunked => {
+                            f.debug_tuple("OutputMode::Chunked").finish()
+                        }
+                    }
+                }
+            }
+            #[derive(Clone)]
+            pub struct ProcessConfig {
+                pub program: _rt::String,
+                pub args: _rt::Vec<_rt::String>,
+                pub cwd: Option<_rt::String>,
+                pub env: _rt::Vec<(_rt::String, _rt::String)>,
+                pub buffer_size: u32,
+                pub stdout_mode: OutputMode,
+                pub stderr_mode: OutputMode,
+                pub chunk_size: Option<u32>,
+            }
+            impl ::core::fmt::Debug for ProcessConfig {
+                fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                    f.debug_struct("ProcessConfig")
+                        .field("program", &self.program)
+                        .field("args", &self.args)
+                        .field("cwd", &self.cwd)
+                        .field("env", &self.env)
+                        .field("buffer-size", &self.buffer_size)
+                        .field("stdout-mode", &self.stdout_mode)
+                        .field("stderr-mode", &self.stderr_mode)
+                        .field("chunk-size", &self.chunk_size)
+                        .finish()
+                }
+            }
             #[allow(unused_unsafe, clippy::all)]
-            /// # Spawn a new child actor
-            ///
-            /// Creates and starts a new actor from the specified manifest file.
-            ///
-            /// ## Parameters
-            ///
-            /// * `manifest` - Path or content of the manifest file describing the actor
-            /// * `init-bytes` - Optional initial state for the actor (serialized bytes)
-            ///
-            /// ## Returns
-            ///
-            /// * `Ok(string)` - ID of the newly created actor
-            /// * `Err(string)` - Error message if spawning fails
-            pub fn spawn(
-                manifest: &str,
-                init_bytes: Option<&[u8]>,
-            ) -> Result<_rt::String, _rt::String> {
+            pub fn os_spawn(config: &ProcessConfig) -> Result<u64, _rt::String> {
                 unsafe {
-                    #[cfg_attr(target_pointer_width = "64", repr(align(8)))]
-                    #[cfg_attr(target_pointer_width = "32", repr(align(4)))]
+                    #[repr(align(8))]
                     struct RetArea(
                         [::core::mem::MaybeUninit<
                             u8,
-                        >; 3 * ::core::mem::size_of::<*const u8>()],
+                        >; 8 + 2 * ::core::mem::size_of::<*const u8>()],
                     );
                     let mut ret_area = RetArea(
-                        [::core::mem::MaybeUninit::uninit(); 3
-                            * ::core::mem::size_of::<*const u8>()],
+                        [::core::mem::MaybeUninit::uninit(); 8
+                            + 2 * ::core::mem::size_of::<*const u8>()],
                     );
-                    let vec0 = manifest;
-                    let ptr0 = vec0.as_ptr().cast::<u8>();
-                    let len0 = vec0.len();
-                    let (result2_0, result2_1, result2_2) = match init_bytes {
+                    let ProcessConfig {
+                        program: program0,
+                        args: args0,
+                        cwd: cwd0,
+                        env: env0,
+                        buffer_size: buffer_size0,
+                        stdout_mode: stdout_mode0,
+                        stderr_mode: stderr_mode0,
+                        chunk_size: chunk_size0,
+                    } = config;
+                    let vec1 = program0;
+                    let ptr1 = vec1.as_ptr().cast::<u8>();
+                    let len1 = vec1.len();
+                    let vec3 = args0;
+                    let len3 = vec3.len();
+                    let layout3 = _rt::alloc::Layout::from_size_align_unchecked(
+                        vec3.len() * (2 * ::core::mem::size_of::<*const u8>()),
+                        ::core::mem::size_of::<*const u8>(),
+                    );
+                    let result3 = if layout3.size() != 0 {
+                        let ptr = _rt::alloc::alloc(layout3).cast::<u8>();
+                        if ptr.is_null() {
+                            _rt::alloc::handle_alloc_error(layout3);
+                        }
+                        ptr
+                    } else {
+                        ::core::ptr::null_mut()
+                    };
+                    for (i, e) in vec3.into_iter().enumerate() {
+                        let base = result3
+                            .add(i * (2 * ::core::mem::size_of::<*const u8>()));
+                        {
+                            let vec2 = e;
+                            let ptr2 = vec2.as_ptr().cast::<u8>();
+                            let len2 = vec2.len();
+                            *base
+                                .add(::core::mem::size_of::<*const u8>())
+                                .cast::<usize>() = len2;
+                            *base.add(0).cast::<*mut u8>() = ptr2.cast_mut();
+                        }
+                    }
+                    let (result5_0, result5_1, result5_2) = match cwd0 {
                         Some(e) => {
-                            let vec1 = e;
-                            let ptr1 = vec1.as_ptr().cast::<u8>();
-                            let len1 = vec1.len();
-                            (1i32, ptr1.cast_mut(), len1)
+                            let vec4 = e;
+                            let ptr4 = vec4.as_ptr().cast::<u8>();
+                            let len4 = vec4.len();
+                            (1i32, ptr4.cast_mut(), len4)
                         }
                         None => (0i32, ::core::ptr::null_mut(), 0usize),
                     };
-                    let ptr3 = ret_area.0.as_mut_ptr().cast::<u8>();
-                    #[cfg(target_arch = "wasm32")]
-                    #[link(wasm_import_module = "ntwk:theater/supervisor")]
-                    unsafe extern "C" {
-                        #[link_name = "spawn"]
-                        fn wit_import4(
-                            _: *mut u8,
-                            _: usize,
-                            _: i32,
-                            _: *mut u8,
-                            _: usize,
-                            _: *mut u8,
-                        );
+                    let vec9 = env0;
+                    let len9 = vec9.len();
+                    let layout9 = _rt::alloc::Layout::from_size_align_unchecked(
+                        vec9.len() * (4 * ::core::mem::size_of::<*const u8>()),
+                        ::core::mem::size_of::<*const u8>(),
+                    );
+                    let result9 = if layout9.size() != 0 {
+                        let ptr = _rt::alloc::alloc(layout9).cast::<u8>();
+                        if ptr.is_null() {
+                            _rt::alloc::handle_alloc_error(layout9);
+                        }
+                        ptr
+                    } else {
+                        ::core::ptr::null_mut()
+                    };
+                    for (i, e) in vec9.into_iter().enumerate() {
+                        let base = result9
+                            .add(i * (4 * ::core::mem::size_of::<*const u8>()));
+                        {
+                            let (t6_0, t6_1) = e;
+                            let vec7 = t6_0;
+                            let ptr7 = vec7.as_ptr().cast::<u8>();
+                            let len7 = vec7.len();
+                            *base
+                                .add(::core::mem::size_of::<*const u8>())
+                                .cast::<usize>() = len7;
+                            *base.add(0).cast::<*mut u8>() = ptr7.cast_mut();
+                            let vec8 = t6_1;
+                            let ptr8 = vec8.as_ptr().cast::<u8>();
+                            let len8 = vec8.len();
+                            *base
+                                .add(3 * ::core::mem::size_of::<*const u8>())
+                                .cast::<usize>() = len8;
+                            *base
+                                .add(2 * ::core::mem::size_of::<*const u8>())
+                                .cast::<*mut u8>() = ptr8.cast_mut();
+                        }
+                    }
+                    let (result10_0, result10_1) = match chunk_size0 {
+                        Some(e) => (1i32, _rt::as_i32(e)),
+                        None => (0i32, 0i32),
+                    };
+                    let ptr11 = ret_area.0.as_mut_ptr().cast::<u8>();
+                    #[cfg(target_arch = "wasm32")]
+                    #[link(wasm_import_module = "ntwk:theater/process")]
+                    unsafe extern "C" {
+                        #[link_name = "os-spawn"]
+                        fn wit_import12(
+                            _: *mut u8,
+                            _: usize,
+                            _: *mut u8,
+                            _: usize,
+                            _: i32,
+                            _: *mut u8,
+                            _: usize,
+                            _: *mut u8,
+                            _: usize,
+                            _: i32,
+                            _: i32,
+                            _: i32,
+                            _: i32,
+                            _: i32,
+                            _: *mut u8,
+                        );
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    unsafe extern "C" fn wit_import12(
+                        _: *mut u8,
+                        _: usize,
+                        _: *mut u8,
+                        _: usize,
+                        _: i32,
+                        _: *mut u8,
+                        _: usize,
+                        _: *mut u8,
+                        _: usize,
+                        _: i32,
+                        _: i32,
+                        _: i32,
+                        _: i32,
+                        _: i32,
+                        _: *mut u8,
+                    ) {
+                        unreachable!()
+                    }
+                    unsafe {
+                        wit_import12(
+                            ptr1.cast_mut(),
+                            len1,
+                            result3,
+                            len3,
+                            result5_0,
+                            result5_1,
+                            result5_2,
+                            result9,
+                            len9,
+                            _rt::as_i32(buffer_size0),
+                            stdout_mode0.clone() as i32,
+                            stderr_mode0.clone() as i32,
+                            result10_0,
+                            result10_1,
+                            ptr11,
+                        )
+                    };
+                    let l13 = i32::from(*ptr11.add(0).cast::<u8>());
+                    let result18 = match l13 {
+                        0 => {
+                            let e = {
+                                let l14 = *ptr11.add(8).cast::<i64>();
+                                l14 as u64
+                            };
+                            Ok(e)
+                        }
+                        1 => {
+                            let e = {
+                                let l15 = *ptr11.add(8).cast::<*mut u8>();
+                                let l16 = *ptr11
+                                    .add(8 + ::core::mem::size_of::<*const u8>())
+                                    .cast::<usize>();
+                                let len17 = l16;
+                                let bytes17 = _rt::Vec::from_raw_parts(
+                                    l15.cast(),
+                                    len17,
+                                    len17,
+                                );
+                                _rt::string_lift(bytes17)
+                            };
+                            Err(e)
+                        }
+                        _ => _rt::invalid_enum_discriminant(),
+                    };
+                    if layout3.size() != 0 {
+                        _rt::alloc::dealloc(result3.cast(), layout3);
+                    }
+                    if layout9.size() != 0 {
+                        _rt::alloc::dealloc(result9.cast(), layout9);
+                    }
+                    result18
+                }
+            }
+        }
+        /// # Supervisor Interface
+        ///
+        /// Defines the interface for actor supervision in the Theater system. This allows parent actors
+        /// to manage the lifecycle and monitor the state of their child actors.
+        ///
+        /// ## Purpose
+        ///
+        /// The supervisor interface enables an important part of the Theater architecture: the supervision
+        /// tree. Similar to Erlang's supervision system, this allows actors to monitor and manage other
+        /// actors, creating a hierarchical structure that enhances fault tolerance and system management.
+        ///
+        /// Through this interface, parent actors can:
+        /// - Spawn new child actors
+        /// - Monitor child actor status and state
+        /// - Restart failed child actors
+        /// - Access child actor event history
+        ///
+        /// ## Example
+        ///
+        /// In a typical Theater actor, supervision capabilities would be used like this:
+        ///
+        /// ```rust
+        /// use ntwk::theater::supervisor;
+        ///
+        /// // Spawn a new child actor from a manifest
+        /// let child_id = supervisor::spawn("child_manifest.toml", None)?;
+        ///
+        /// // Later, restart the child if needed
+        /// supervisor::restart_child(child_id)?;
+        ///
+        /// // Get the current state of the child
+        /// let state = supervisor::get_child_state(child_id)?;
+        /// ```
+        ///
+        /// ## Security
+        ///
+        /// The supervisor interface has significant privileges, as it can control other actors and
+        /// access their state. The Theater runtime ensures that an actor can only supervise its
+        /// direct children, enforcing proper hierarchy boundaries.
+        ///
+        /// ## Implementation Notes
+        ///
+        /// The interface uses string-based actor identifiers and returns results that can contain
+        /// errors as strings. This allows for human-readable error messages and flexible actor
+        /// identification across the system.
+        #[allow(dead_code, async_fn_in_trait, unused_imports, clippy::all)]
+        pub mod supervisor {
+            #[used]
+            #[doc(hidden)]
+            static __FORCE_SECTION_REF: fn() = super::super::super::__link_custom_section_describing_imports;
+            use super::super::super::_rt;
+            pub type ChainEvent = super::super::super::ntwk::theater::types::ChainEvent;
+            #[allow(unused_unsafe, clippy::all)]
+            /// # Spawn a new child actor
+            ///
+            /// Creates and starts a new actor from the specified manifest file.
+            ///
+            /// ## Parameters
+            ///
+            /// * `manifest` - Path or content of the manifest file describing the actor
+            /// * `init-bytes` - Optional initial state for the actor (serialized bytes)
+            ///
+            /// ## Returns
+            ///
+            /// * `Ok(string)` - ID of the newly created actor
+            /// * `Err(string)` - Error message if spawning fails
+            pub fn spawn(
+                manifest: &str,
+                init_bytes: Option<&[u8]>,
+            ) -> Result<_rt::String, _rt::String> {
+                unsafe {
+                    #[cfg_attr(target_pointer_width = "64", repr(align(8)))]
+                    #[cfg_attr(target_pointer_width = "32", repr(align(4)))]
+                    struct RetArea(
+                        [::core::mem::MaybeUninit<
+                            u8,
+                        >; 3 * ::core::mem::size_of::<*const u8>()],
+                    );
+                    let mut ret_area = RetArea(
+                        [::core::mem::MaybeUninit::uninit(); 3
+                            * ::core::mem::size_of::<*const u8>()],
+                    );
+                    let vec0 = manifest;
+                    let ptr0 = vec0.as_ptr().cast::<u8>();
+                    let len0 = vec0.len();
+                    let (result2_0, result2_1, result2_2) = match init_bytes {
+                        Some(e) => {
+                            let vec1 = e;
+                            let ptr1 = vec1.as_ptr().cast::<u8>();
+                            let len1 = vec1.len();
+                            (1i32, ptr1.cast_mut(), len1)
+                        }
+                        None => (0i32, ::core::ptr::null_mut(), 0usize),
+                    };
+                    let ptr3 = ret_area.0.as_mut_ptr().cast::<u8>();
+                    #[cfg(target_arch = "wasm32")]
+                    #[link(wasm_import_module = "ntwk:theater/supervisor")]
+                    unsafe extern "C" {
+                        #[link_name = "spawn"]
+                        fn wit_import4(
+                            _: *mut u8,
+                            _: usize,
+                            _: i32,
+                            _: *mut u8,
+                            _: usize,
+                            _: *mut u8,
+                        );
                     }
                     #[cfg(not(target_arch = "wasm32"))]
                     unsafe extern "C" fn wit_import4(
@@ -7868,35 +8262,535 @@ pub mod exports {
                                         }
                                     };
                                 }
-                            }
-                            *ptr6
-                                .add(5 * ::core::mem::size_of::<*const u8>())
-                                .cast::<usize>() = len15;
-                            *ptr6
-                                .add(4 * ::core::mem::size_of::<*const u8>())
-                                .cast::<*mut u8>() = result15;
+                            }
+                            *ptr6
+                                .add(5 * ::core::mem::size_of::<*const u8>())
+                                .cast::<usize>() = len15;
+                            *ptr6
+                                .add(4 * ::core::mem::size_of::<*const u8>())
+                                .cast::<*mut u8>() = result15;
+                        }
+                        Err(e) => {
+                            *ptr6.add(0).cast::<u8>() = (1i32) as u8;
+                            let vec16 = (e.into_bytes()).into_boxed_slice();
+                            let ptr16 = vec16.as_ptr().cast::<u8>();
+                            let len16 = vec16.len();
+                            ::core::mem::forget(vec16);
+                            *ptr6
+                                .add(2 * ::core::mem::size_of::<*const u8>())
+                                .cast::<usize>() = len16;
+                            *ptr6
+                                .add(::core::mem::size_of::<*const u8>())
+                                .cast::<*mut u8>() = ptr16.cast_mut();
+                        }
+                    };
+                    ptr6
+                }
+                #[doc(hidden)]
+                #[allow(non_snake_case)]
+                pub unsafe fn __post_return_handle_websocket_message<T: Guest>(
+                    arg0: *mut u8,
+                ) {
+                    let l0 = i32::from(*arg0.add(0).cast::<u8>());
+                    match l0 {
+                        0 => {
+                            let l1 = i32::from(
+                                *arg0.add(::core::mem::size_of::<*const u8>()).cast::<u8>(),
+                            );
+                            match l1 {
+                                0 => {}
+                                _ => {
+                                    let l2 = *arg0
+                                        .add(2 * ::core::mem::size_of::<*const u8>())
+                                        .cast::<*mut u8>();
+                                    let l3 = *arg0
+                                        .add(3 * ::core::mem::size_of::<*const u8>())
+                                        .cast::<usize>();
+                                    let base4 = l2;
+                                    let len4 = l3;
+                                    _rt::cabi_dealloc(base4, len4 * 1, 1);
+                                }
+                            }
+                            let l5 = *arg0
+                                .add(4 * ::core::mem::size_of::<*const u8>())
+                                .cast::<*mut u8>();
+                            let l6 = *arg0
+                                .add(5 * ::core::mem::size_of::<*const u8>())
+                                .cast::<usize>();
+                            let base17 = l5;
+                            let len17 = l6;
+                            for i in 0..len17 {
+                                let base = base17
+                                    .add(i * (9 * ::core::mem::size_of::<*const u8>()));
+                                {
+                                    let l7 = i32::from(*base.add(0).cast::<u8>());
+                                    match l7 {
+                                        0 => {}
+                                        1 => {}
+                                        2 => {}
+                                        3 => {}
+                                        4 => {}
+                                        5 => {}
+                                        _ => {
+                                            let l8 = *base
+                                                .add(::core::mem::size_of::<*const u8>())
+                                                .cast::<*mut u8>();
+                                            let l9 = *base
+                                                .add(2 * ::core::mem::size_of::<*const u8>())
+                                                .cast::<usize>();
+                                            _rt::cabi_dealloc(l8, l9, 1);
+                                        }
+                                    }
+                                    let l10 = i32::from(
+                                        *base
+                                            .add(3 * ::core::mem::size_of::<*const u8>())
+                                            .cast::<u8>(),
+                                    );
+                                    match l10 {
+                                        0 => {}
+                                        _ => {
+                                            let l11 = *base
+                                                .add(4 * ::core::mem::size_of::<*const u8>())
+                                                .cast::<*mut u8>();
+                                            let l12 = *base
+                                                .add(5 * ::core::mem::size_of::<*const u8>())
+                                                .cast::<usize>();
+                                            let base13 = l11;
+                                            let len13 = l12;
+                                            _rt::cabi_dealloc(base13, len13 * 1, 1);
+                                        }
+                                    }
+                                    let l14 = i32::from(
+                                        *base
+                                            .add(6 * ::core::mem::size_of::<*const u8>())
+                                            .cast::<u8>(),
+                                    );
+                                    match l14 {
+                                        0 => {}
+                                        _ => {
+                                            let l15 = *base
+                                                .add(7 * ::core::mem::size_of::<*const u8>())
+                                                .cast::<*mut u8>();
+                                            let l16 = *base
+                                                .add(8 * ::core::mem::size_of::<*const u8>())
+                                                .cast::<usize>();
+                                            _rt::cabi_dealloc(l15, l16, 1);
+                                        }
+                                    }
+                                }
+                            }
+                            _rt::cabi_dealloc(
+                                base17,
+                                len17 * (9 * ::core::mem::size_of::<*const u8>()),
+                                ::core::mem::size_of::<*const u8>(),
+                            );
+                        }
+                        _ => {
+                            let l18 = *arg0
+                                .add(::core::mem::size_of::<*const u8>())
+                                .cast::<*mut u8>();
+                            let l19 = *arg0
+                                .add(2 * ::core::mem::size_of::<*const u8>())
+                                .cast::<usize>();
+                            _rt::cabi_dealloc(l18, l19, 1);
+                        }
+                    }
+                }
+                #[doc(hidden)]
+                #[allow(non_snake_case)]
+                pub unsafe fn _export_handle_websocket_disconnect_cabi<T: Guest>(
+                    arg0: i32,
+                    arg1: *mut u8,
+                    arg2: usize,
+                    arg3: i64,
+                    arg4: i64,
+                ) -> *mut u8 {
+                    #[cfg(target_arch = "wasm32")] _rt::run_ctors_once();
+                    let result1 = T::handle_websocket_disconnect(
+                        match arg0 {
+                            0 => None,
+                            1 => {
+                                let e = {
+                                    let len0 = arg2;
+                                    _rt::Vec::from_raw_parts(arg1.cast(), len0, len0)
+                                };
+                                Some(e)
+                            }
+                            _ => _rt::invalid_enum_discriminant(),
+                        },
+                        (arg3 as u64, arg4 as u64),
+                    );
+                    let ptr2 = (&raw mut _RET_AREA.0).cast::<u8>();
+                    match result1 {
+                        Ok(e) => {
+                            *ptr2.add(0).cast::<u8>() = (0i32) as u8;
+                            let (t3_0,) = e;
+                            match t3_0 {
+                                Some(e) => {
+                                    *ptr2
+                                        .add(::core::mem::size_of::<*const u8>())
+                                        .cast::<u8>() = (1i32) as u8;
+                                    let vec4 = (e).into_boxed_slice();
+                                    let ptr4 = vec4.as_ptr().cast::<u8>();
+                                    let len4 = vec4.len();
+                                    ::core::mem::forget(vec4);
+                                    *ptr2
+                                        .add(3 * ::core::mem::size_of::<*const u8>())
+                                        .cast::<usize>() = len4;
+                                    *ptr2
+                                        .add(2 * ::core::mem::size_of::<*const u8>())
+                                        .cast::<*mut u8>() = ptr4.cast_mut();
+                                }
+                                None => {
+                                    *ptr2
+                                        .add(::core::mem::size_of::<*const u8>())
+                                        .cast::<u8>() = (0i32) as u8;
+                                }
+                            };
+                        }
+                        Err(e) => {
+                            *ptr2.add(0).cast::<u8>() = (1i32) as u8;
+                            let vec5 = (e.into_bytes()).into_boxed_slice();
+                            let ptr5 = vec5.as_ptr().cast::<u8>();
+                            let len5 = vec5.len();
+                            ::core::mem::forget(vec5);
+                            *ptr2
+                                .add(2 * ::core::mem::size_of::<*const u8>())
+                                .cast::<usize>() = len5;
+                            *ptr2
+                                .add(::core::mem::size_of::<*const u8>())
+                                .cast::<*mut u8>() = ptr5.cast_mut();
+                        }
+                    };
+                    ptr2
+                }
+                #[doc(hidden)]
+                #[allow(non_snake_case)]
+                pub unsafe fn __post_return_handle_websocket_disconnect<T: Guest>(
+                    arg0: *mut u8,
+                ) {
+                    let l0 = i32::from(*arg0.add(0).cast::<u8>());
+                    match l0 {
+                        0 => {
+                            let l1 = i32::from(
+                                *arg0.add(::core::mem::size_of::<*const u8>()).cast::<u8>(),
+                            );
+                            match l1 {
+                                0 => {}
+                                _ => {
+                                    let l2 = *arg0
+                                        .add(2 * ::core::mem::size_of::<*const u8>())
+                                        .cast::<*mut u8>();
+                                    let l3 = *arg0
+                                        .add(3 * ::core::mem::size_of::<*const u8>())
+                                        .cast::<usize>();
+                                    let base4 = l2;
+                                    let len4 = l3;
+                                    _rt::cabi_dealloc(base4, len4 * 1, 1);
+                                }
+                            }
+                        }
+                        _ => {
+                            let l5 = *arg0
+                                .add(::core::mem::size_of::<*const u8>())
+                                .cast::<*mut u8>();
+                            let l6 = *arg0
+                                .add(2 * ::core::mem::size_of::<*const u8>())
+                                .cast::<usize>();
+                            _rt::cabi_dealloc(l5, l6, 1);
+                        }
+                    }
+                }
+                pub trait Guest {
+                    fn handle_request(
+                        state: State,
+                        params: (HandlerId, HttpRequest),
+                    ) -> Result<(State, (HttpResponse,)), _rt::String>;
+                    fn handle_middleware(
+                        state: State,
+                        params: (HandlerId, HttpRequest),
+                    ) -> Result<(State, (MiddlewareResult,)), _rt::String>;
+                    fn handle_websocket_connect(
+                        state: State,
+                        params: (HandlerId, u64, _rt::String, Option<_rt::String>),
+                    ) -> Result<(State,), _rt::String>;
+                    fn handle_websocket_message(
+                        state: State,
+                        params: (HandlerId, u64, WebsocketMessage),
+                    ) -> Result<(State, (_rt::Vec<WebsocketMessage>,)), _rt::String>;
+                    fn handle_websocket_disconnect(
+                        state: State,
+                        params: (HandlerId, u64),
+                    ) -> Result<(State,), _rt::String>;
+                }
+                #[doc(hidden)]
+                macro_rules! __export_ntwk_theater_http_handlers_cabi {
+                    ($ty:ident with_types_in $($path_to_types:tt)*) => {
+                        const _ : () = { #[unsafe (export_name =
+                        "ntwk:theater/http-handlers#handle-request")] unsafe extern "C"
+                        fn export_handle_request(arg0 : i32, arg1 : * mut u8, arg2 :
+                        usize, arg3 : i64, arg4 : * mut u8, arg5 : usize, arg6 : * mut
+                        u8, arg7 : usize, arg8 : * mut u8, arg9 : usize, arg10 : i32,
+                        arg11 : * mut u8, arg12 : usize,) -> * mut u8 { unsafe {
+                        $($path_to_types)*:: _export_handle_request_cabi::<$ty > (arg0,
+                        arg1, arg2, arg3, arg4, arg5, arg6, arg7, arg8, arg9, arg10,
+                        arg11, arg12) } } #[unsafe (export_name =
+                        "cabi_post_ntwk:theater/http-handlers#handle-request")] unsafe
+                        extern "C" fn _post_return_handle_request(arg0 : * mut u8,) {
+                        unsafe { $($path_to_types)*:: __post_return_handle_request::<$ty
+                        > (arg0) } } #[unsafe (export_name =
+                        "ntwk:theater/http-handlers#handle-middleware")] unsafe extern
+                        "C" fn export_handle_middleware(arg0 : i32, arg1 : * mut u8, arg2
+                        : usize, arg3 : i64, arg4 : * mut u8, arg5 : usize, arg6 : * mut
+                        u8, arg7 : usize, arg8 : * mut u8, arg9 : usize, arg10 : i32,
+                        arg11 : * mut u8, arg12 : usize,) -> * mut u8 { unsafe {
+                        $($path_to_types)*:: _export_handle_middleware_cabi::<$ty >
+                        (arg0, arg1, arg2, arg3, arg4, arg5, arg6, arg7, arg8, arg9,
+                        arg10, arg11, arg12) } } #[unsafe (export_name =
+                        "cabi_post_ntwk:theater/http-handlers#handle-middleware")] unsafe
+                        extern "C" fn _post_return_handle_middleware(arg0 : * mut u8,) {
+                        unsafe { $($path_to_types)*::
+                        __post_return_handle_middleware::<$ty > (arg0) } } #[unsafe
+                        (export_name =
+                        "ntwk:theater/http-handlers#handle-websocket-connect")] unsafe
+                        extern "C" fn export_handle_websocket_connect(arg0 : i32, arg1 :
+                        * mut u8, arg2 : usize, arg3 : i64, arg4 : i64, arg5 : * mut u8,
+                        arg6 : usize, arg7 : i32, arg8 : * mut u8, arg9 : usize,) -> *
+                        mut u8 { unsafe { $($path_to_types)*::
+                        _export_handle_websocket_connect_cabi::<$ty > (arg0, arg1, arg2,
+                        arg3, arg4, arg5, arg6, arg7, arg8, arg9) } } #[unsafe
+                        (export_name =
+                        "cabi_post_ntwk:theater/http-handlers#handle-websocket-connect")]
+                        unsafe extern "C" fn _post_return_handle_websocket_connect(arg0 :
+                        * mut u8,) { unsafe { $($path_to_types)*::
+                        __post_return_handle_websocket_connect::<$ty > (arg0) } }
+                        #[unsafe (export_name =
+                        "ntwk:theater/http-handlers#handle-websocket-message")] unsafe
+                        extern "C" fn export_handle_websocket_message(arg0 : i32, arg1 :
+                        * mut u8, arg2 : usize, arg3 : i64, arg4 : i64, arg5 : i32, arg6
+                        : * mut u8, arg7 : usize, arg8 : i32, arg9 : * mut u8, arg10 :
+                        usize, arg11 : i32, arg12 : * mut u8, arg13 : usize,) -> * mut u8
+                        { unsafe { $($path_to_types)*::
+                        _export_handle_websocket_message_cabi::<$ty > (arg0, arg1, arg2,
+                        arg3, arg4, arg5, arg6, arg7, arg8, arg9, arg10, arg11, arg12,
+                        arg13) } } #[unsafe (export_name =
+                        "cabi_post_ntwk:theater/http-handlers#handle-websocket-message")]
+                        unsafe extern "C" fn _post_return_handle_websocket_message(arg0 :
+                        * mut u8,) { unsafe { $($path_to_types)*::
+                        __post_return_handle_websocket_message::<$ty > (arg0) } }
+                        #[unsafe (export_name =
+                        "ntwk:theater/http-handlers#handle-websocket-disconnect")] unsafe
+                        extern "C" fn export_handle_websocket_disconnect(arg0 : i32, arg1
+                        : * mut u8, arg2 : usize, arg3 : i64, arg4 : i64,) -> * mut u8 {
+                        unsafe { $($path_to_types)*::
+                        _export_handle_websocket_disconnect_cabi::<$ty > (arg0, arg1,
+                        arg2, arg3, arg4) } } #[unsafe (export_name =
+                        "cabi_post_ntwk:theater/http-handlers#handle-websocket-disconnect")]
+                        unsafe extern "C" fn
+                        _post_return_handle_websocket_disconnect(arg0 : * mut u8,) {
+                        unsafe { $($path_to_types)*::
+                        __post_return_handle_websocket_disconnect::<$ty > (arg0) } } };
+                    };
+                }
+                #[doc(hidden)]
+                pub(crate) use __export_ntwk_theater_http_handlers_cabi;
+                #[cfg_attr(target_pointer_width = "64", repr(align(8)))]
+                #[cfg_attr(target_pointer_width = "32", repr(align(4)))]
+                struct _RetArea(
+                    [::core::mem::MaybeUninit<
+                        u8,
+                    >; 14 * ::core::mem::size_of::<*const u8>()],
+                );
+                static mut _RET_AREA: _RetArea = _RetArea(
+                    [::core::mem::MaybeUninit::uninit(); 14
+                        * ::core::mem::size_of::<*const u8>()],
+                );
+            }
+            #[allow(dead_code, async_fn_in_trait, unused_imports, clippy::all)]
+            pub mod process_handlers {
+                #[used]
+                #[doc(hidden)]
+                static __FORCE_SECTION_REF: fn() = super::super::super::super::__link_custom_section_describing_imports;
+                use super::super::super::super::_rt;
+                pub type Json = super::super::super::super::ntwk::theater::types::Json;
+                #[doc(hidden)]
+                #[allow(non_snake_case)]
+                pub unsafe fn _export_handle_stdout_cabi<T: Guest>(
+                    arg0: i32,
+                    arg1: *mut u8,
+                    arg2: usize,
+                    arg3: i64,
+                    arg4: *mut u8,
+                    arg5: usize,
+                ) -> *mut u8 {
+                    #[cfg(target_arch = "wasm32")] _rt::run_ctors_once();
+                    let len1 = arg5;
+                    let result3 = T::handle_stdout(
+                        match arg0 {
+                            0 => None,
+                            1 => {
+                                let e = {
+                                    let len0 = arg2;
+                                    _rt::Vec::from_raw_parts(arg1.cast(), len0, len0)
+                                };
+                                Some(e)
+                            }
+                            _ => _rt::invalid_enum_discriminant(),
+                        },
+                        (arg3 as u64, _rt::Vec::from_raw_parts(arg4.cast(), len1, len1)),
+                    );
+                    let ptr3 = (&raw mut _RET_AREA.0).cast::<u8>();
+                    match result3 {
+                        Ok(e) => {
+                            *ptr3.add(0).cast::<u8>() = (0i32) as u8;
+                            let (t3_0,) = e;
+                            match t3_0 {
+                                Some(e) => {
+                                    *ptr3
+                                        .add(::core::mem::size_of::<*const u8>())
+                                        .cast::<u8>() = (1i32) as u8;
+                                    let vec3_1 = (e).into_boxed_slice();
+                                    let ptr3_1 = vec3_1.as_ptr().cast::<u8>();
+                                    let len3_1 = vec3_1.len();
+                                    ::core::mem::forget(vec3_1);
+                                    *ptr3
+                                        .add(3 * ::core::mem::size_of::<*const u8>())
+                                        .cast::<usize>() = len3_1;
+                                    *ptr3
+                                        .add(2 * ::core::mem::size_of::<*const u8>())
+                                        .cast::<*mut u8>() = ptr3_1.cast_mut();
+                                }
+                                None => {
+                                    *ptr3
+                                        .add(::core::mem::size_of::<*const u8>())
+                                        .cast::<u8>() = (0i32) as u8;
+                                }
+                            };
+                        }
+                        Err(e) => {
+                            *ptr3.add(0).cast::<u8>() = (1i32) as u8;
+                            let vec3_2 = (e.into_bytes()).into_boxed_slice();
+                            let ptr3_2 = vec3_2.as_ptr().cast::<u8>();
+                            let len3_2 = vec3_2.len();
+                            ::core::mem::forget(vec3_2);
+                            *ptr3
+                                .add(2 * ::core::mem::size_of::<*const u8>())
+                                .cast::<usize>() = len3_2;
+                            *ptr3
+                                .add(::core::mem::size_of::<*const u8>())
+                                .cast::<*mut u8>() = ptr3_2.cast_mut();
+                        }
+                    };
+                    ptr3
+                }
+                #[doc(hidden)]
+                #[allow(non_snake_case)]
+                pub unsafe fn __post_return_handle_stdout<T: Guest>(arg0: *mut u8) {
+                    let l0 = i32::from(*arg0.add(0).cast::<u8>());
+                    match l0 {
+                        0 => {
+                            let l1 = i32::from(
+                                *arg0.add(::core::mem::size_of::<*const u8>()).cast::<u8>(),
+                            );
+                            match l1 {
+                                0 => {}
+                                _ => {
+                                    let l2 = *arg0
+                                        .add(2 * ::core::mem::size_of::<*const u8>())
+                                        .cast::<*mut u8>();
+                                    let l3 = *arg0
+                                        .add(3 * ::core::mem::size_of::<*const u8>())
+                                        .cast::<usize>();
+                                    let base4 = l2;
+                                    let len4 = l3;
+                                    _rt::cabi_dealloc(base4, len4 * 1, 1);
+                                }
+                            }
+                        }
+                        _ => {
+                            let l5 = *arg0
+                                .add(::core::mem::size_of::<*const u8>())
+                                .cast::<*mut u8>();
+                            let l6 = *arg0
+                                .add(2 * ::core::mem::size_of::<*const u8>())
+                                .cast::<usize>();
+                            _rt::cabi_dealloc(l5, l6, 1);
+                        }
+                    }
+                }
+                #[doc(hidden)]
+                #[allow(non_snake_case)]
+                pub unsafe fn _export_handle_stderr_cabi<T: Guest>(
+                    arg0: i32,
+                    arg1: *mut u8,
+                    arg2: usize,
+                    arg3: i64,
+                    arg4: *mut u8,
+                    arg5: usize,
+                ) -> *mut u8 {
+                    #[cfg(target_arch = "wasm32")] _rt::run_ctors_once();
+                    let len1 = arg5;
+                    let result3 = T::handle_stderr(
+                        match arg0 {
+                            0 => None,
+                            1 => {
+                                let e = {
+                                    let len0 = arg2;
+                                    _rt::Vec::from_raw_parts(arg1.cast(), len0, len0)
+                                };
+                                Some(e)
+                            }
+                            _ => _rt::invalid_enum_discriminant(),
+                        },
+                        (arg3 as u64, _rt::Vec::from_raw_parts(arg4.cast(), len1, len1)),
+                    );
+                    let ptr3 = (&raw mut _RET_AREA.0).cast::<u8>();
+                    match result3 {
+                        Ok(e) => {
+                            *ptr3.add(0).cast::<u8>() = (0i32) as u8;
+                            let (t3_0,) = e;
+                            match t3_0 {
+                                Some(e) => {
+                                    *ptr3
+                                        .add(::core::mem::size_of::<*const u8>())
+                                        .cast::<u8>() = (1i32) as u8;
+                                    let vec3_1 = (e).into_boxed_slice();
+                                    let ptr3_1 = vec3_1.as_ptr().cast::<u8>();
+                                    let len3_1 = vec3_1.len();
+                                    ::core::mem::forget(vec3_1);
+                                    *ptr3
+                                        .add(3 * ::core::mem::size_of::<*const u8>())
+                                        .cast::<usize>() = len3_1;
+                                    *ptr3
+                                        .add(2 * ::core::mem::size_of::<*const u8>())
+                                        .cast::<*mut u8>() = ptr3_1.cast_mut();
+                                }
+                                None => {
+                                    *ptr3
+                                        .add(::core::mem::size_of::<*const u8>())
+                                        .cast::<u8>() = (0i32) as u8;
+                                }
+                            };
                         }
                         Err(e) => {
-                            *ptr6.add(0).cast::<u8>() = (1i32) as u8;
-                            let vec16 = (e.into_bytes()).into_boxed_slice();
-                            let ptr16 = vec16.as_ptr().cast::<u8>();
-                            let len16 = vec16.len();
-                            ::core::mem::forget(vec16);
-                            *ptr6
+                            *ptr3.add(0).cast::<u8>() = (1i32) as u8;
+                            let vec3_2 = (e.into_bytes()).into_boxed_slice();
+                            let ptr3_2 = vec3_2.as_ptr().cast::<u8>();
+                            let len3_2 = vec3_2.len();
+                            ::core::mem::forget(vec3_2);
+                            *ptr3
                                 .add(2 * ::core::mem::size_of::<*const u8>())
-                                .cast::<usize>() = len16;
-                            *ptr6
+                                .cast::<usize>() = len3_2;
+                            *ptr3
                                 .add(::core::mem::size_of::<*const u8>())
-                                .cast::<*mut u8>() = ptr16.cast_mut();
+                                .cast::<*mut u8>() = ptr3_2.cast_mut();
                         }
                     };
-                    ptr6
+                    ptr3
                 }
                 #[doc(hidden)]
                 #[allow(non_snake_case)]
-                pub unsafe fn __post_return_handle_websocket_message<T: Guest>(
-                    arg0: *mut u8,
-                ) {
+                pub unsafe fn __post_return_handle_stderr<T: Guest>(arg0: *mut u8) {
                     let l0 = i32::from(*arg0.add(0).cast::<u8>());
                     match l0 {
                         0 => {
@@ -7917,102 +8811,29 @@ pub mod exports {
                                     _rt::cabi_dealloc(base4, len4 * 1, 1);
                                 }
                             }
-                            let l5 = *arg0
-                                .add(4 * ::core::mem::size_of::<*const u8>())
-                                .cast::<*mut u8>();
-                            let l6 = *arg0
-                                .add(5 * ::core::mem::size_of::<*const u8>())
-                                .cast::<usize>();
-                            let base17 = l5;
-                            let len17 = l6;
-                            for i in 0..len17 {
-                                let base = base17
-                                    .add(i * (9 * ::core::mem::size_of::<*const u8>()));
-                                {
-                                    let l7 = i32::from(*base.add(0).cast::<u8>());
-                                    match l7 {
-                                        0 => {}
-                                        1 => {}
-                                        2 => {}
-                                        3 => {}
-                                        4 => {}
-                                        5 => {}
-                                        _ => {
-                                            let l8 = *base
-                                                .add(::core::mem::size_of::<*const u8>())
-                                                .cast::<*mut u8>();
-                                            let l9 = *base
-                                                .add(2 * ::core::mem::size_of::<*const u8>())
-                                                .cast::<usize>();
-                                            _rt::cabi_dealloc(l8, l9, 1);
-                                        }
-                                    }
-                                    let l10 = i32::from(
-                                        *base
-                                            .add(3 * ::core::mem::size_of::<*const u8>())
-                                            .cast::<u8>(),
-                                    );
-                                    match l10 {
-                                        0 => {}
-                                        _ => {
-                                            let l11 = *base
-                                                .add(4 * ::core::mem::size_of::<*const u8>())
-                                                .cast::<*mut u8>();
-                                            let l12 = *base
-                                                .add(5 * ::core::mem::size_of::<*const u8>())
-                                                .cast::<usize>();
-                                            let base13 = l11;
-                                            let len13 = l12;
-                                            _rt::cabi_dealloc(base13, len13 * 1, 1);
-                                        }
-                                    }
-                                    let l14 = i32::from(
-                                        *base
-                                            .add(6 * ::core::mem::size_of::<*const u8>())
-                                            .cast::<u8>(),
-                                    );
-                                    match l14 {
-                                        0 => {}
-                                        _ => {
-                                            let l15 = *base
-                                                .add(7 * ::core::mem::size_of::<*const u8>())
-                                                .cast::<*mut u8>();
-                                            let l16 = *base
-                                                .add(8 * ::core::mem::size_of::<*const u8>())
-                                                .cast::<usize>();
-                                            _rt::cabi_dealloc(l15, l16, 1);
-                                        }
-                                    }
-                                }
-                            }
-                            _rt::cabi_dealloc(
-                                base17,
-                                len17 * (9 * ::core::mem::size_of::<*const u8>()),
-                                ::core::mem::size_of::<*const u8>(),
-                            );
                         }
                         _ => {
-                            let l18 = *arg0
+                            let l5 = *arg0
                                 .add(::core::mem::size_of::<*const u8>())
                                 .cast::<*mut u8>();
-                            let l19 = *arg0
+                            let l6 = *arg0
                                 .add(2 * ::core::mem::size_of::<*const u8>())
                                 .cast::<usize>();
-                            _rt::cabi_dealloc(l18, l19, 1);
+                            _rt::cabi_dealloc(l5, l6, 1);
                         }
                     }
                 }
                 #[doc(hidden)]
                 #[allow(non_snake_case)]
-                pub unsafe fn _export_handle_websocket_disconnect_cabi<T: Guest>(
+                pub unsafe fn _export_handle_exit_cabi<T: Guest>(
                     arg0: i32,
                     arg1: *mut u8,
                     arg2: usize,
                     arg3: i64,
-                    arg4: i64,
+                    arg4: i32,
                 ) -> *mut u8 {
                     #[cfg(target_arch = "wasm32")] _rt::run_ctors_once();
-                    let result1 = T::handle_websocket_disconnect(
+                    let result3 = T::handle_exit(
                         match arg0 {
                             0 => None,
                             1 => {
@@ -8024,57 +8845,55 @@ pub mod exports {
                             }
                             _ => _rt::invalid_enum_discriminant(),
                         },
-                        (arg3 as u64, arg4 as u64),
+                        (arg3 as u64, arg4),
                     );
-                    let ptr2 = (&raw mut _RET_AREA.0).cast::<u8>();
-                    match result1 {
+                    let ptr3 = (&raw mut _RET_AREA.0).cast::<u8>();
+                    match result3 {
                         Ok(e) => {
-                            *ptr2.add(0).cast::<u8>() = (0i32) as u8;
+                            *ptr3.add(0).cast::<u8>() = (0i32) as u8;
                             let (t3_0,) = e;
                             match t3_0 {
                                 Some(e) => {
-                                    *ptr2
+                                    *ptr3
                                         .add(::core::mem::size_of::<*const u8>())
                                         .cast::<u8>() = (1i32) as u8;
-                                    let vec4 = (e).into_boxed_slice();
-                                    let ptr4 = vec4.as_ptr().cast::<u8>();
-                                    let len4 = vec4.len();
-                                    ::core::mem::forget(vec4);
-                                    *ptr2
+                                    let vec3_1 = (e).into_boxed_slice();
+                                    let ptr3_1 = vec3_1.as_ptr().cast::<u8>();
+                                    let len3_1 = vec3_1.len();
+                                    ::core::mem::forget(vec3_1);
+                                    *ptr3
                                         .add(3 * ::core::mem::size_of::<*const u8>())
-                                        .cast::<usize>() = len4;
-                                    *ptr2
+                                        .cast::<usize>() = len3_1;
+                                    *ptr3
                                         .add(2 * ::core::mem::size_of::<*const u8>())
-                                        .cast::<*mut u8>() = ptr4.cast_mut();
+                                        .cast::<*mut u8>() = ptr3_1.cast_mut();
                                 }
                                 None => {
-                                    *ptr2
+                                    *ptr3
                                         .add(::core::mem::size_of::<*const u8>())
                                         .cast::<u8>() = (0i32) as u8;
                                 }
                             };
                         }
                         Err(e) => {
-                            *ptr2.add(0).cast::<u8>() = (1i32) as u8;
-                            let vec5 = (e.into_bytes()).into_boxed_slice();
-                            let ptr5 = vec5.as_ptr().cast::<u8>();
-                            let len5 = vec5.len();
-                            ::core::mem::forget(vec5);
-                            *ptr2
+                            *ptr3.add(0).cast::<u8>() = (1i32) as u8;
+                            let vec3_2 = (e.into_bytes()).into_boxed_slice();
+                            let ptr3_2 = vec3_2.as_ptr().cast::<u8>();
+                            let len3_2 = vec3_2.len();
+                            ::core::mem::forget(vec3_2);
+                            *ptr3
                                 .add(2 * ::core::mem::size_of::<*const u8>())
-                                .cast::<usize>() = len5;
-                            *ptr2
+                                .cast::<usize>() = len3_2;
+                            *ptr3
                                 .add(::core::mem::size_of::<*const u8>())
-                                .cast::<*mut u8>() = ptr5.cast_mut();
+                                .cast::<*mut u8>() = ptr3_2.cast_mut();
                         }
                     };
-                    ptr2
+                    ptr3
                 }
                 #[doc(hidden)]
                 #[allow(non_snake_case)]
-                pub unsafe fn __post_return_handle_websocket_disconnect<T: Guest>(
-                    arg0: *mut u8,
-                ) {
+                pub unsafe fn __post_return_handle_exit<T: Guest>(arg0: *mut u8) {
                     let l0 = i32::from(*arg0.add(0).cast::<u8>());
                     match l0 {
                         0 => {
@@ -8108,107 +8927,61 @@ pub mod exports {
                     }
                 }
                 pub trait Guest {
-                    fn handle_request(
-                        state: State,
-                        params: (HandlerId, HttpRequest),
-                    ) -> Result<(State, (HttpResponse,)), _rt::String>;
-                    fn handle_middleware(
-                        state: State,
-                        params: (HandlerId, HttpRequest),
-                    ) -> Result<(State, (MiddlewareResult,)), _rt::String>;
-                    fn handle_websocket_connect(
-                        state: State,
-                        params: (HandlerId, u64, _rt::String, Option<_rt::String>),
-                    ) -> Result<(State,), _rt::String>;
-                    fn handle_websocket_message(
-                        state: State,
-                        params: (HandlerId, u64, WebsocketMessage),
-                    ) -> Result<(State, (_rt::Vec<WebsocketMessage>,)), _rt::String>;
-                    fn handle_websocket_disconnect(
-                        state: State,
-                        params: (HandlerId, u64),
-                    ) -> Result<(State,), _rt::String>;
+                    fn handle_stdout(
+                        state: Option<Json>,
+                        params: (u64, _rt::Vec<u8>),
+                    ) -> Result<(Option<Json>,), _rt::String>;
+                    fn handle_stderr(
+                        state: Option<Json>,
+                        params: (u64, _rt::Vec<u8>),
+                    ) -> Result<(Option<Json>,), _rt::String>;
+                    fn handle_exit(
+                        state: Option<Json>,
+                        params: (u64, i32),
+                    ) -> Result<(Option<Json>,), _rt::String>;
                 }
                 #[doc(hidden)]
-                macro_rules! __export_ntwk_theater_http_handlers_cabi {
+                macro_rules! __export_ntwk_theater_process_handlers_cabi {
                     ($ty:ident with_types_in $($path_to_types:tt)*) => {
-                        const _ : () = { #[unsafe (export_name =
-                        "ntwk:theater/http-handlers#handle-request")] unsafe extern "C"
-                        fn export_handle_request(arg0 : i32, arg1 : * mut u8, arg2 :
-                        usize, arg3 : i64, arg4 : * mut u8, arg5 : usize, arg6 : * mut
-                        u8, arg7 : usize, arg8 : * mut u8, arg9 : usize, arg10 : i32,
-                        arg11 : * mut u8, arg12 : usize,) -> * mut u8 { unsafe {
-                        $($path_to_types)*:: _export_handle_request_cabi::<$ty > (arg0,
-                        arg1, arg2, arg3, arg4, arg5, arg6, arg7, arg8, arg9, arg10,
-                        arg11, arg12) } } #[unsafe (export_name =
-                        "cabi_post_ntwk:theater/http-handlers#handle-request")] unsafe
-                        extern "C" fn _post_return_handle_request(arg0 : * mut u8,) {
-                        unsafe { $($path_to_types)*:: __post_return_handle_request::<$ty
-                        > (arg0) } } #[unsafe (export_name =
-                        "ntwk:theater/http-handlers#handle-middleware")] unsafe extern
-                        "C" fn export_handle_middleware(arg0 : i32, arg1 : * mut u8, arg2
-                        : usize, arg3 : i64, arg4 : * mut u8, arg5 : usize, arg6 : * mut
-                        u8, arg7 : usize, arg8 : * mut u8, arg9 : usize, arg10 : i32,
-                        arg11 : * mut u8, arg12 : usize,) -> * mut u8 { unsafe {
-                        $($path_to_types)*:: _export_handle_middleware_cabi::<$ty >
-                        (arg0, arg1, arg2, arg3, arg4, arg5, arg6, arg7, arg8, arg9,
-                        arg10, arg11, arg12) } } #[unsafe (export_name =
-                        "cabi_post_ntwk:theater/http-handlers#handle-middleware")] unsafe
-                        extern "C" fn _post_return_handle_middleware(arg0 : * mut u8,) {
-                        unsafe { $($path_to_types)*::
-                        __post_return_handle_middleware::<$ty > (arg0) } } #[unsafe
-                        (export_name =
-                        "ntwk:theater/http-handlers#handle-websocket-connect")] unsafe
-                        extern "C" fn export_handle_websocket_connect(arg0 : i32, arg1 :
-                        * mut u8, arg2 : usize, arg3 : i64, arg4 : i64, arg5 : * mut u8,
-                        arg6 : usize, arg7 : i32, arg8 : * mut u8, arg9 : usize,) -> *
-                        mut u8 { unsafe { $($path_to_types)*::
-                        _export_handle_websocket_connect_cabi::<$ty > (arg0, arg1, arg2,
-                        arg3, arg4, arg5, arg6, arg7, arg8, arg9) } } #[unsafe
-                        (export_name =
-                        "cabi_post_ntwk:theater/http-handlers#handle-websocket-connect")]
-                        unsafe extern "C" fn _post_return_handle_websocket_connect(arg0 :
-                        * mut u8,) { unsafe { $($path_to_types)*::
-                        __post_return_handle_websocket_connect::<$ty > (arg0) } }
+                        const _ : () = {
                         #[unsafe (export_name =
-                        "ntwk:theater/http-handlers#handle-websocket-message")] unsafe
-                        extern "C" fn export_handle_websocket_message(arg0 : i32, arg1 :
-                        * mut u8, arg2 : usize, arg3 : i64, arg4 : i64, arg5 : i32, arg6
-                        : * mut u8, arg7 : usize, arg8 : i32, arg9 : * mut u8, arg10 :
-                        usize, arg11 : i32, arg12 : * mut u8, arg13 : usize,) -> * mut u8
-                        { unsafe { $($path_to_types)*::
-                        _export_handle_websocket_message_cabi::<$ty > (arg0, arg1, arg2,
-                        arg3, arg4, arg5, arg6, arg7, arg8, arg9, arg10, arg11, arg12,
-                        arg13) } } #[unsafe (export_name =
-                        "cabi_post_ntwk:theater/http-handlers#handle-websocket-message")]
-                        unsafe extern "C" fn _post_return_handle_websocket_message(arg0 :
-                        * mut u8,) { unsafe { $($path_to_types)*::
-                        __post_return_handle_websocket_message::<$ty > (arg0) } }
+                        "ntwk:theater/process-handlers#handle-stdout")] unsafe extern "C" fn
+                        export_handle_stdout(arg0 : i32, arg1 : * mut u8, arg2 : usize, arg3 : i64, arg4 : * mut u8, arg5 : usize,) -> * mut u8 { unsafe {
+                        $($path_to_types)*:: _export_handle_stdout_cabi::<$ty > (arg0, arg1, arg2, arg3, arg4, arg5) } }
                         #[unsafe (export_name =
-                        "ntwk:theater/http-handlers#handle-websocket-disconnect")] unsafe
-                        extern "C" fn export_handle_websocket_disconnect(arg0 : i32, arg1
-                        : * mut u8, arg2 : usize, arg3 : i64, arg4 : i64,) -> * mut u8 {
-                        unsafe { $($path_to_types)*::
-                        _export_handle_websocket_disconnect_cabi::<$ty > (arg0, arg1,
-                        arg2, arg3, arg4) } } #[unsafe (export_name =
-                        "cabi_post_ntwk:theater/http-handlers#handle-websocket-disconnect")]
-                        unsafe extern "C" fn
-                        _post_return_handle_websocket_disconnect(arg0 : * mut u8,) {
-                        unsafe { $($path_to_types)*::
-                        __post_return_handle_websocket_disconnect::<$ty > (arg0) } } };
+                        "cabi_post_ntwk:theater/process-handlers#handle-stdout")] unsafe extern
+                        "C" fn _post_return_handle_stdout(arg0 : * mut u8,) { unsafe {
+                        $($path_to_types)*:: __post_return_handle_stdout::<$ty > (arg0) } }
+                        #[unsafe (export_name =
+                        "ntwk:theater/process-handlers#handle-stderr")] unsafe extern "C" fn
+                        export_handle_stderr(arg0 : i32, arg1 : * mut u8, arg2 : usize, arg3 : i64, arg4 : * mut u8, arg5 : usize,) -> * mut u8 { unsafe {
+                        $($path_to_types)*:: _export_handle_stderr_cabi::<$ty > (arg0, arg1, arg2, arg3, arg4, arg5) } }
+                        #[unsafe (export_name =
+                        "cabi_post_ntwk:theater/process-handlers#handle-stderr")] unsafe extern
+                        "C" fn _post_return_handle_stderr(arg0 : * mut u8,) { unsafe {
+                        $($path_to_types)*:: __post_return_handle_stderr::<$ty > (arg0) } }
+                        #[unsafe (export_name =
+                        "ntwk:theater/process-handlers#handle-exit")] unsafe extern "C" fn
+                        export_handle_exit(arg0 : i32, arg1 : * mut u8, arg2 : usize, arg3 : i64, arg4 : i32,) -> * mut u8 { unsafe {
+                        $($path_to_types)*:: _export_handle_exit_cabi::<$ty > (arg0, arg1, arg2, arg3, arg4) } }
+                        #[unsafe (export_name =
+                        "cabi_post_ntwk:theater/process-handlers#handle-exit")] unsafe extern
+                        "C" fn _post_return_handle_exit(arg0 : * mut u8,) { unsafe {
+                        $($path_to_types)*:: __post_return_handle_exit::<$ty > (arg0) } }
+                        };
                     };
                 }
                 #[doc(hidden)]
-                pub(crate) use __export_ntwk_theater_http_handlers_cabi;
+                pub(crate) use __export_ntwk_theater_process_handlers_cabi;
                 #[cfg_attr(target_pointer_width = "64", repr(align(8)))]
                 #[cfg_attr(target_pointer_width = "32", repr(align(4)))]
                 struct _RetArea(
                     [::core::mem::MaybeUninit<
                         u8,
-                    >; 14 * ::core::mem::size_of::<*const u8>()],
+                    >; 4 * ::core::mem::size_of::<*const u8>()],
                 );
                 static mut _RET_AREA: _RetArea = _RetArea(
-                    [::core::mem::MaybeUninit::uninit(); 14
+                    [::core::mem::MaybeUninit::uninit(); 4
                         * ::core::mem::size_of::<*const u8>()],
                 );
             }
@@ -8559,6 +9332,9 @@ macro_rules! __export_chat_interface_impl {
         exports::ntwk::theater::http_handlers::__export_ntwk_theater_http_handlers_cabi!($ty
         with_types_in $($path_to_types_root)*:: exports::ntwk::theater::http_handlers);
         $($path_to_types_root)*::
+        exports::ntwk::theater::process_handlers::__export_ntwk_theater_process_handlers_cabi!($ty
+        with_types_in $($path_to_types_root)*:: exports::ntwk::theater::process_handlers);
+        $($path_to_types_root)*::
         exports::ntwk::theater::supervisor_handlers::__export_ntwk_theater_supervisor_handlers_cabi!($ty
         with_types_in $($path_to_types_root)*::
         exports::ntwk::theater::supervisor_handlers);
@@ -0,0 +1,373 @@
+use crate::forward_to_chat_state;
+use crate::protocol::{BranchNode, ChatMessage, ChatStateRequest, ChatStateResponse};
+use genai_types::messages::MessageContent;
+use genai_types::Message;
+use std::collections::HashMap;
+
+/// Number of characters of message text shown in branch previews
+const PREVIEW_CHARS: usize = 80;
+
+/// Fetch a single message from a chat-state actor
+pub fn get_message(actor_id: &str, message_id: &str) -> Result<ChatMessage, String> {
+    let req = ChatStateRequest::GetMessage {
+        message_id: message_id.to_string(),
+    };
+    match forward_to_chat_state(actor_id, &req)? {
+        ChatStateResponse::ChatMessage { message } => Ok(message),
+        ChatStateResponse::Error { error } => Err(format!(
+            "Error retrieving message {}: {}",
+            message_id, error.message
+        )),
+        _ => Err(format!(
+            "Unexpected response when retrieving message {}",
+            message_id
+        )),
+    }
+}
+
+/// Walk the parent links from a message back to the root of the chain.
+/// The returned messages are ordered oldest first and end with `message_id`.
+pub fn get_ancestry(actor_id: &str, message_id: &str) -> Result<Vec<ChatMessage>, String> {
+    let (chain, _) = walk_back(message_id, None, usize::MAX, |id| get_message(actor_id, id))?;
+    Ok(chain)
+}
+
+/// Walk back at most `limit` messages from `start`, returning them oldest first.
+/// If older messages remain, the ID of the oldest message in the page is returned
+/// as the cursor for the next page.
+pub fn get_page(
+    actor_id: &str,
+    start: &str,
+    limit: usize,
+) -> Result<(Vec<ChatMessage>, Option<String>), String> {
+    walk_back(start, None, limit, |id| get_message(actor_id, id))
+}
+
+/// Follow parent links from `start` until `base`, the root or `limit` messages.
+/// Returns the messages oldest first, and the ID of the oldest one if its parent
+/// was not reached.
+fn walk_back<F>(
+    start: &str,
+    base: Option<&str>,
+    limit: usize,
+    mut fetch: F,
+) -> Result<(Vec<ChatMessage>, Option<String>), String>
+where
+    F: FnMut(&str) -> Result<ChatMessage, String>,
+{
+    let mut messages: Vec<ChatMessage> = Vec::new();
+    let mut next = Some(start.to_string());
+
+    while let Some(id) = next.take() {
+        if Some(id.as_str()) == base {
+            break;
+        }
+        let message = fetch(&id)?;
+        next = message.parent_id.clone();
+        messages.push(message);
+
+        if messages.len() == limit {
+            break;
+        }
+    }
+
+    let cursor = match next {
+        Some(_) => messages.last().and_then(|oldest| oldest.id.clone()),
+        None => None,
+    };
+
+    messages.reverse();
+    Ok((messages, cursor))
+}
+
+/// Move the head of a chat-state actor's chain. New messages are appended under it.
+pub fn set_head(actor_id: &str, head: Option<String>) -> Result<(), String> {
+    match forward_to_chat_state(actor_id, &ChatStateRequest::SetHead { head })? {
+        ChatStateResponse::Success | ChatStateResponse::Head { .. } => Ok(()),
+        ChatStateResponse::Error { error } => {
+            Err(format!("Error moving conversation head: {}", error.message))
+        }
+        _ => Err("Unexpected response when moving conversation head".to_string()),
+    }
+}
+
+/// Get the current head of a chat-state actor's chain
+pub fn get_head(actor_id: &str) -> Result<Option<String>, String> {
+    match forward_to_chat_state(actor_id, &ChatStateRequest::GetHead)? {
+        ChatStateResponse::Head { head } => Ok(head),
+        ChatStateResponse::Error { error } => {
+            Err(format!("Error retrieving head: {}", error.message))
+        }
+        _ => Err("Unexpected response when retrieving head".to_string()),
+    }
+}
+
+/// Get the messages on the current chain of a chat-state actor, oldest first
+pub fn get_history(actor_id: &str) -> Result<Vec<ChatMessage>, String> {
+    match forward_to_chat_state(actor_id, &ChatStateRequest::GetHistory)? {
+        ChatStateResponse::History { messages } => Ok(messages),
+        ChatStateResponse::Error { error } => {
+            Err(format!("Error retrieving history: {}", error.message))
+        }
+        _ => Err("Unexpected response when retrieving message history".to_string()),
+    }
+}
+
+/// Messages of a chain that come after `base`, the whole chain if there is no base
+/// or it isn't part of the chain
+pub fn messages_after(chain: &[ChatMessage], base: Option<&str>) -> Vec<ChatMessage> {
+    let start = base
+        .and_then(|base| chain.iter().position(|m| m.id.as_deref() == Some(base)))
+        .map_or(0, |index| index + 1);
+    chain[start..].to_vec()
+}
+
+/// Build the tree made up of every chain ending at one of `heads`.
+/// The current chain is loaded in one request, only messages on other
+/// branches are fetched one by one.
+pub fn get_tree(actor_id: &str, heads: &[String]) -> Result<Vec<BranchNode>, String> {
+    let mut cache: HashMap<String, ChatMessage> = get_history(actor_id)?
+        .into_iter()
+        .filter_map(|message| message.id.clone().map(|id| (id, message)))
+        .collect();
+
+    build_tree(heads, |id| match cache.remove(id) {
+        Some(message) => Ok(message),
+        None => get_message(actor_id, id),
+    })
+}
+
+/// Build the tree from the chains ending at `heads`, fetching each message once.
+/// Nodes are returned parents first, each with the IDs of its children.
+fn build_tree<F>(heads: &[String], mut fetch: F) -> Result<Vec<BranchNode>, String>
+where
+    F: FnMut(&str) -> Result<ChatMessage, String>,
+{
+    let mut messages: HashMap<String, ChatMessage> = HashMap::new();
+    let mut order: Vec<String> = Vec::new();
+
+    for head in heads {
+        // Walk back until we reach a message we have already seen
+        let mut branch = Vec::new();
+        let mut next = Some(head.clone());
+        while let Some(id) = next {
+            if messages.contains_key(&id) {
+                break;
+            }
+            let message = fetch(&id)?;
+            next = message.parent_id.clone();
+            messages.insert(id.clone(), message);
+            branch.push(id);
+        }
+
+        branch.reverse();
+        order.extend(branch);
+    }
+
+    let mut nodes: Vec<BranchNode> = order
+        .iter()
+        .map(|id| {
+            let message = &messages[id];
+            BranchNode {
+                id: id.clone(),
+                parent_id: message.parent_id.clone(),
+                role: message.message.role.clone(),
+                preview: preview_text(&message.message, PREVIEW_CHARS),
+                children: Vec::new(),
+            }
+        })
+        .collect();
+
+    let index: HashMap<String, usize> = order
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (id.clone(), i))
+        .collect();
+    for i in 0..nodes.len() {
+        if let Some(parent) = nodes[i].parent_id.as_ref().and_then(|p| index.get(p)) {
+            let child_id = nodes[i].id.clone();
+            nodes[*parent].children.push(child_id);
+        }
+    }
+
+    Ok(nodes)
+}
+
+/// Text blocks of a message joined with spaces
+pub fn message_text(message: &Message) -> String {
+    message
+        .content
+        .iter()
+        .filter_map(|content| match content {
+            MessageContent::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Text of a message, truncated to `max_chars` characters
+pub fn preview_text(message: &Message, max_chars: usize) -> String {
+    let text = message_text(message);
+
+    if text.chars().count() > max_chars {
+        let truncated: String = text.chars().take(max_chars.saturating_sub(3)).collect();
+        format!("{}...", truncated)
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_message(role: &str, text: &str) -> Message {
+        Message {
+            role: role.to_string(),
+            content: vec![MessageContent::Text {
+                text: text.to_string(),
+            }],
+        }
+    }
+
+    fn chat_message(id: &str, parent_id: Option<&str>, role: &str) -> ChatMessage {
+        ChatMessage {
+            id: Some(id.to_string()),
+            parent_id: parent_id.map(String::from),
+            message: text_message(role, id),
+            truncated: false,
+        }
+    }
+
+    /// m1 <- m2 <- m3 <- m4, with m5 branching off m2
+    fn store() -> HashMap<String, ChatMessage> {
+        [
+            chat_message("m1", None, "user"),
+            chat_message("m2", Some("m1"), "assistant"),
+            chat_message("m3", Some("m2"), "user"),
+            chat_message("m4", Some("m3"), "assistant"),
+            chat_message("m5", Some("m2"), "user"),
+        ]
+        .into_iter()
+        .map(|message| (message.id.clone().unwrap(), message))
+        .collect()
+    }
+
+    fn fetch_from(
+        store: &HashMap<String, ChatMessage>,
+    ) -> impl FnMut(&str) -> Result<ChatMessage, String> + '_ {
+        |id| {
+            store
+                .get(id)
+                .cloned()
+                .ok_or_else(|| format!("unknown message {}", id))
+        }
+    }
+
+    fn ids(messages: &[ChatMessage]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|message| message.id.as_deref().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn walk_back_to_root_is_oldest_first() {
+        let store = store();
+        let (messages, cursor) = walk_back("m4", None, usize::MAX, fetch_from(&store)).unwrap();
+        assert_eq!(ids(&messages), vec!["m1", "m2", "m3", "m4"]);
+        assert_eq!(cursor, None);
+    }
+
+    #[test]
+    fn walk_back_stops_at_base() {
+        let store = store();
+        let (messages, cursor) =
+            walk_back("m4", Some("m2"), usize::MAX, fetch_from(&store)).unwrap();
+        assert_eq!(ids(&messages), vec!["m3", "m4"]);
+        assert_eq!(cursor, None);
+    }
+
+    #[test]
+    fn walk_back_pages_with_cursor() {
+        let store = store();
+        let (page, cursor) = walk_back("m4", None, 2, fetch_from(&store)).unwrap();
+        assert_eq!(ids(&page), vec!["m3", "m4"]);
+        assert_eq!(cursor.as_deref(), Some("m3"));
+
+        let (page, cursor) = walk_back("m2", None, 2, fetch_from(&store)).unwrap();
+        assert_eq!(ids(&page), vec!["m1", "m2"]);
+        assert_eq!(cursor, None);
+    }
+
+    #[test]
+    fn walk_back_reports_missing_messages() {
+        let store = store();
+        assert!(walk_back("m9", None, usize::MAX, fetch_from(&store)).is_err());
+    }
+
+    #[test]
+    fn build_tree_links_branches_and_fetches_once() {
+        let store = store();
+        let mut fetched = Vec::new();
+        let heads = vec!["m4".to_string(), "m5".to_string()];
+        let nodes = build_tree(&heads, |id| {
+            fetched.push(id.to_string());
+            fetch_from(&store)(id)
+        })
+        .unwrap();
+
+        let order: Vec<&str> = nodes.iter().map(|node| node.id.as_str()).collect();
+        assert_eq!(order, vec!["m1", "m2", "m3", "m4", "m5"]);
+        assert_eq!(nodes[1].children, vec!["m3".to_string(), "m5".to_string()]);
+        assert!(nodes[3].children.is_empty());
+        assert_eq!(fetched.len(), 5);
+    }
+
+    #[test]
+    fn messages_after_slices_past_base() {
+        let store = store();
+        let chain: Vec<ChatMessage> = ["m1", "m2", "m3", "m4"]
+            .iter()
+            .map(|id| store[*id].clone())
+            .collect();
+
+        assert_eq!(ids(&messages_after(&chain, Some("m2"))), vec!["m3", "m4"]);
+        assert!(messages_after(&chain, Some("m4")).is_empty());
+        assert_eq!(messages_after(&chain, None).len(), 4);
+        assert_eq!(messages_after(&chain, Some("m5")).len(), 4);
+    }
+
+    #[test]
+    fn preview_text_keeps_short_text() {
+        assert_eq!(preview_text(&text_message("user", "hello"), 10), "hello");
+    }
+
+    #[test]
+    fn preview_text_truncates_on_char_boundaries() {
+        let message = text_message("user", "héllo wörld ünïcode");
+        let preview = preview_text(&message, 8);
+        assert_eq!(preview, "héllo...");
+        assert_eq!(preview.chars().count(), 8);
+
+        let emoji = text_message("user", "🙂🙂🙂🙂🙂🙂");
+        assert_eq!(preview_text(&emoji, 5), "🙂🙂...");
+    }
+
+    #[test]
+    fn message_text_joins_text_blocks() {
+        let message = Message {
+            role: "user".to_string(),
+            content: vec![
+                MessageContent::Text {
+                    text: "one".to_string(),
+                },
+                MessageContent::Text {
+                    text: "two".to_string(),
+                },
+            ],
+        };
+        assert_eq!(message_text(&message), "one two");
+    }
+}
@@ -1,38 +1,75 @@
 mod bindings;
+mod chain;
+mod models;
 mod protocol;
 mod state;
+mod streaming;
+mod titling;
+mod validation;
 
 use crate::bindings::exports::ntwk::theater::actor::Guest;
 use crate::bindings::exports::ntwk::theater::http_handlers::Guest as HttpHandlersGuest;
 use crate::bindings::exports::ntwk::theater::message_server_client::Guest as MessageServerClient;
+use crate::bindings::exports::ntwk::theater::process_handlers::Guest as ProcessHandlersGuest;
 use crate::bindings::exports::ntwk::theater::supervisor_handlers::Guest as SupervisorHandlersGuest;
+use crate::bindings::ntwk::theater::filesystem;
 use crate::bindings::ntwk::theater::http_framework::{
-    add_route, create_server, enable_websocket, register_handler, start_server, ServerConfig,
+    add_route, close_websocket, create_server, enable_websocket, register_handler,
+    send_websocket_message, start_server, ServerConfig,
 };
-use crate::bindings::ntwk::theater::http_types::{HttpRequest, HttpResponse, MiddlewareResult};
-use crate::bindings::ntwk::theater::message_server_host::request;
+use crate::bindings::ntwk::theater::http_types::{
+    HttpRequest, HttpResponse, MiddlewareResult, TlsConfig,
+};
+use crate::bindings::ntwk::theater::message_server_host::{close_channel, request};
+use crate::bindings::ntwk::theater::process::{os_spawn, OutputMode, ProcessConfig};
 use crate::bindings::ntwk::theater::runtime::log;
 use crate::bindings::ntwk::theater::store;
-use crate::bindings::ntwk::theater::supervisor::spawn;
+use crate::bindings::ntwk::theater::supervisor::{spawn, stop_child};
 use crate::bindings::ntwk::theater::timing::now;
 use crate::bindings::ntwk::theater::websocket_types::{MessageType, WebsocketMessage};
 use bindings::exports::ntwk::theater::supervisor_handlers::WitActorError;
 use genai_types::Message;
 
 use protocol::{
-    create_conversation_created_message, create_conversation_response, create_error_message,
-    create_messages_response, create_settings_response, create_success_response, ChatMessage,
-    ChatStateRequest, ChatStateResponse, ClientMessage, ServerMessage,
+    create_conversation_archived_message, create_conversation_created_message,
+    create_conversation_deleted_message, create_conversation_list_changed_message,
+    create_conversation_response, create_error_message, create_messages_response,
+    create_preset_list_message, create_settings_invalid_message, create_settings_response,
+    create_success_response, create_welcome_message, negotiate_protocol_version, BranchNode,
+    ChatMessage, ChatStateRequest, ChatStateResponse, ChatStateUpdate, ClientEnvelope,
+    ClientMessage, ConversationListChange, ConversationSettings, ForkOrigin, ServerEnvelope,
+    ServerMessage, TitleSource, DELTA_REPLIES_VERSION, MAX_PAGE_LIMIT, MIN_PROTOCOL_VERSION,
 };
 use serde::{Deserialize, Serialize};
 use state::{
-    add_connection, get_actor_id_for_conversation, initialize_state, register_conversation_actor,
-    remove_connection, set_active_conversation, store_state, InterfaceState,
+    add_connection, get_actor_id_for_conversation, get_connections_for_conversation,
+    get_subscription_for_conversation, initialize_state, register_conversation_actor,
+    remove_connection, set_active_conversation, set_protocol_version, store_state, ChatStateConfig,
+    InterfaceState,
 };
+use std::collections::HashMap;
+
+// How often idle connections are looked for, in milliseconds
+const IDLE_SWEEP_INTERVAL_MS: u64 = 30 * 1000;
+
+// Chat-state manifest used when the init state doesn't configure one
+const DEFAULT_CHAT_STATE_MANIFEST: &str = "chat-state/manifest.toml";
 
 #[derive(Serialize, Deserialize, Debug)]
 struct InitState {
     store_id: Option<String>,
+
+    /// Model catalog to use instead of the built-in one
+    #[serde(default)]
+    models: Option<Vec<models::ModelInfo>>,
+
+    /// How to spawn chat-state actors, required
+    #[serde(default)]
+    chat_state: Option<ChatStateConfig>,
+
+    /// HTTP server settings, defaults to plain HTTP on 0.0.0.0:8080
+    #[serde(default)]
+    server: Option<state::ServerConfig>,
 }
 
 use sha1::{Digest, Sha1};
@@ -55,33 +92,48 @@ impl Guest for Component {
             },
             None => {
                 log("No init state provided, using default");
-                InitState { store_id: None }
+                InitState {
+                    store_id: None,
+                    models: None,
+                    chat_state: None,
+                    server: None,
+                }
             }
         };
 
         let store_id = match init_state.store_id {
             Some(id) => id,
-            None => store::new().expect("Failed to create new store"),
+            None => store::new().map_err(|e| format!("Failed to create new store: {}", e))?,
         };
 
-        let interface_state = initialize_state(&store_id);
-
-        // Serialize state
-        let state_bytes = match serde_json::to_vec(&interface_state) {
-            Ok(bytes) => bytes,
-            Err(e) => return Err(format!("Failed to serialize state: {}", e)),
-        };
+        let chat_state_config = check_chat_state_config(init_state.chat_state)?;
+        let mut server_config = init_state
+            .server
+            .unwrap_or_else(state::default_server_config);
+        check_server_config(&server_config)?;
+        server_config.base_path = normalize_base_path(&server_config.base_path);
+        let mut interface_state = initialize_state(
+            &store_id,
+            server_config,
+            chat_state_config,
+            init_state.models,
+        )?;
 
         // Set up HTTP server
+        let server_config = &interface_state.server_config;
         let config = ServerConfig {
-            port: Some(interface_state.server_config.port as u16),
-            host: Some(interface_state.server_config.host.clone()),
-            tls_config: None,
+            port: Some(server_config.port),
+            host: Some(server_config.host.clone()),
+            tls_config: server_config.tls.as_ref().map(|tls| TlsConfig {
+                cert_path: tls.cert_path.clone(),
+                key_path: tls.key_path.clone(),
+            }),
         };
 
         // Create a new HTTP server
         let server_id = create_server(&config)?;
         log(&format!("Created server with ID: {}", server_id));
+        interface_state.server_id = Some(server_id);
 
         // Register handlers
         // Make sure we use the EXACT names that match our exported trait implementation methods
@@ -95,19 +147,35 @@ impl Guest for Component {
             api_handler_id, ws_connect_handler_id, ws_message_handler_id, ws_disconnect_handler_id
         ));
 
-        // Add routes
-        add_route(server_id, "/", "GET", api_handler_id)?;
-        add_route(server_id, "/", "GET", api_handler_id)?;
-        add_route(server_id, "/index.html", "GET", api_handler_id)?;
-        add_route(server_id, "/styles.css", "GET", api_handler_id)?;
-        add_route(server_id, "/bundle.js", "GET", api_handler_id)?;
-        add_route(server_id, "/bundle.js.map", "GET", api_handler_id)?;
-        add_route(server_id, "/api/conversations", "GET", api_handler_id)?;
-        add_route(server_id, "/api/health", "GET", api_handler_id)?;
+        // Add routes, all of them under the base path
+        let base_path = interface_state.server_config.base_path.clone();
+        let route = |path: &str| format!("{}{}", base_path, path);
+        if !base_path.is_empty() {
+            add_route(server_id, &base_path, "GET", api_handler_id)?;
+        }
+        add_route(server_id, &route("/"), "GET", api_handler_id)?;
+        add_route(server_id, &route("/index.html"), "GET", api_handler_id)?;
+        add_route(server_id, &route("/styles.css"), "GET", api_handler_id)?;
+        add_route(server_id, &route("/bundle.js"), "GET", api_handler_id)?;
+        add_route(server_id, &route("/bundle.js.map"), "GET", api_handler_id)?;
+        add_route(
+            server_id,
+            &route("/api/conversations"),
+            "GET",
+            api_handler_id,
+        )?;
+        add_route(
+            server_id,
+            &route("/api/conversations/{id}"),
+            "DELETE",
+            api_handler_id,
+        )?;
+        add_route(server_id, &route("/api/models"), "GET", api_handler_id)?;
+        add_route(server_id, &route("/api/health"), "GET", api_handler_id)?;
         // Enable WebSocket support
         enable_websocket(
             server_id,
-            "/ws",
+            &route("/ws"),
             Some(ws_connect_handler_id),    // Connect handler
             ws_message_handler_id,          // Message handler
             Some(ws_disconnect_handler_id), // Disconnect handler
@@ -117,6 +185,14 @@ impl Guest for Component {
         let port = start_server(server_id)?;
         log(&format!("Server started on port {}", port));
 
+        start_sweep_timer(&mut interface_state);
+
+        // Serialize state
+        let state_bytes = match serde_json::to_vec(&interface_state) {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(format!("Failed to serialize state: {}", e)),
+        };
+
         Ok((Some(state_bytes),))
     }
 }
@@ -136,9 +212,11 @@ impl HttpHandlersGuest for Component {
         // Parse the URI to get the path and query
         let mut path_parts = request.uri.splitn(2, '?');
         let path = path_parts.next().unwrap_or("/");
+        let query = path_parts.next().unwrap_or("");
+        let query_params = parse_query(query);
 
         // Parse state
-        let interface_state: InterfaceState = match state.clone() {
+        let mut interface_state: InterfaceState = match state {
             Some(bytes) => match serde_json::from_slice(&bytes) {
                 Ok(s) => s,
                 Err(e) => return Err(format!("Failed to parse state: {}", e)),
@@ -146,15 +224,64 @@ impl HttpHandlersGuest for Component {
             None => return Err("Missing state".to_string()),
         };
 
+        sweep_idle_connections(&mut interface_state);
+
+        // Route relative to the base path. The bare base path is redirected to its
+        // trailing-slash form so the page's relative asset URLs resolve under it.
+        let base_path = interface_state.server_config.base_path.clone();
+        let path = match path.strip_prefix(base_path.as_str()) {
+            Some("") => "",
+            Some(relative) => relative,
+            None => path,
+        };
+
         // Route handling
         let response = match path {
+            "" => HttpResponse {
+                status: 301,
+                headers: vec![(
+                    "Location".to_string(),
+                    if query.is_empty() {
+                        format!("{}/", base_path)
+                    } else {
+                        format!("{}/?{}", base_path, query)
+                    },
+                )],
+                body: None,
+            },
+            p if request.method == "DELETE" && p.starts_with("/api/conversations/") => {
+                let conversation_id = &p["/api/conversations/".len()..];
+                if delete_conversation(&mut interface_state, conversation_id, None)? {
+                    let json = serde_json::json!({ "deleted": conversation_id }).to_string();
+                    HttpResponse {
+                        status: 200,
+                        headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+                        body: Some(json.as_bytes().to_vec()),
+                    }
+                } else {
+                    HttpResponse {
+                        status: 404,
+                        headers: vec![("Content-Type".to_string(), "text/plain".to_string())],
+                        body: Some("Conversation not found".as_bytes().to_vec()),
+                    }
+                }
+            }
             "/" | "/index.html" => {
-                // Serve HTML chat interface
+                // Serve HTML chat interface, telling it where the WebSocket lives
                 let html = include_str!("../assets/index.html");
+                let html = if base_path.is_empty() {
+                    html.to_string()
+                } else {
+                    let base_script = format!(
+                        "<head>\n    <script>window.CHAT_BASE_PATH = {};</script>",
+                        serde_json::Value::from(base_path.as_str())
+                    );
+                    html.replacen("<head>", &base_script, 1)
+                };
                 HttpResponse {
                     status: 200,
                     headers: vec![("Content-Type".to_string(), "text/html".to_string())],
-                    body: Some(html.as_bytes().to_vec()),
+                    body: Some(html.into_bytes()),
                 }
             }
             "/styles.css" => {
@@ -188,21 +315,25 @@ impl HttpHandlersGuest for Component {
                 }
             }
             "/api/conversations" => {
-                // Return list of conversations
-                let conversations: Vec<serde_json::Value> = interface_state
-                    .conversation_metadata
-                    .iter()
-                    .map(|(id, meta)| {
-                        serde_json::json!({
-                            "id": id,
-                            "title": meta.title,
-                            "created_at": meta.created_at,
-                            "updated_at": meta.updated_at,
-                            "message_count": meta.message_count,
-                            "last_message_preview": meta.last_message_preview
+                // Return list of conversations, archived ones only when asked for
+                let include_archived = query_params
+                    .get("include_archived")
+                    .is_some_and(|v| v == "true" || v == "1");
+                let conversations: Vec<serde_json::Value> =
+                    state::list_conversations(&interface_state, include_archived)
+                        .iter()
+                        .map(|(id, meta)| {
+                            serde_json::json!({
+                                "id": id,
+                                "title": meta.title,
+                                "created_at": meta.created_at,
+                                "updated_at": meta.updated_at,
+                                "message_count": meta.message_count,
+                                "last_message_preview": meta.last_message_preview,
+                                "archived": meta.archived
+                            })
                         })
-                    })
-                    .collect();
+                        .collect();
 
                 let json =
                     serde_json::to_string(&conversations).unwrap_or_else(|_| "[]".to_string());
@@ -213,11 +344,24 @@ impl HttpHandlersGuest for Component {
                     body: Some(json.as_bytes().to_vec()),
                 }
             }
+            "/api/models" => {
+                // Return the model catalog
+                let json = serde_json::to_string(&interface_state.models)
+                    .unwrap_or_else(|_| "[]".to_string());
+
+                HttpResponse {
+                    status: 200,
+                    headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+                    body: Some(json.as_bytes().to_vec()),
+                }
+            }
             "/api/health" => {
                 // Health check endpoint
                 let health = serde_json::json!({
                     "status": "ok",
                     "connections": interface_state.connections.len(),
+                    "peak_connections": interface_state.peak_connections,
+                    "max_connections": interface_state.server_config.max_connections,
                     "conversations": interface_state.conversation_actors.len()
                 });
 
@@ -240,7 +384,13 @@ impl HttpHandlersGuest for Component {
             }
         };
 
-        Ok((state, (response,)))
+        // Serialize updated state
+        let updated_state = match serde_json::to_vec(&interface_state) {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(format!("Failed to serialize state: {}", e)),
+        };
+
+        Ok((Some(updated_state), (response,)))
     }
 
     fn handle_middleware(
@@ -267,7 +417,7 @@ impl HttpHandlersGuest for Component {
         state: Option<Vec<u8>>,
         params: (u64, u64, String, Option<String>),
     ) -> Result<(Option<Vec<u8>>,), String> {
-        let (handler_id, connection_id, path, _query) = params;
+        let (handler_id, connection_id, path, query) = params;
         log(&format!(
             "WebSocket connected - Handler: {}, Connection: {}, Path: {}",
             handler_id, connection_id, path
@@ -282,8 +432,112 @@ impl HttpHandlersGuest for Component {
             None => return Err("Missing state".to_string()),
         };
 
-        // Add connection to state
-        add_connection(&mut interface_state, connection_id, now());
+        // Free the slots of connections that went quiet before counting
+        sweep_idle_connections(&mut interface_state);
+
+        // Refuse the socket once the server is full
+        if state::at_connection_limit(&interface_state) {
+            log(&format!(
+                "Rejecting connection {}: limit of {} connections reached",
+                connection_id, interface_state.server_config.max_connections
+            ));
+            let full_msg = create_error_message(
+                "",
+                "The server has reached its connection limit, try again later",
+                "TOO_MANY_CONNECTIONS",
+            );
+            if let Err(e) = send_to_connection(&interface_state, connection_id, &full_msg) {
+                log(&format!(
+                    "Failed to send rejection to connection {}: {}",
+                    connection_id, e
+                ));
+            }
+            if let Some(server_id) = interface_state.server_id {
+                if let Err(e) = close_websocket(server_id, connection_id) {
+                    log(&format!(
+                        "Failed to close connection {}: {}",
+                        connection_id, e
+                    ));
+                }
+            }
+
+            let updated_state = match serde_json::to_vec(&interface_state) {
+                Ok(bytes) => bytes,
+                Err(e) => return Err(format!("Failed to serialize state: {}", e)),
+            };
+            return Ok((Some(updated_state),));
+        }
+
+        // Clients may ask for a protocol version with ?protocol=<n>
+        let query_params = parse_query(query.as_deref().unwrap_or(""));
+        let requested_version = match query_params.get("protocol") {
+            Some(version) => match version.parse::<u32>() {
+                Ok(version) => Some(version),
+                Err(_) => {
+                    log(&format!("Ignoring invalid protocol version: {}", version));
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let negotiated = negotiate_protocol_version(requested_version);
+        // Keep the connection on failure so the client can still send a `hello`
+        let protocol_version = negotiated.clone().unwrap_or(MIN_PROTOCOL_VERSION);
+        add_connection(&mut interface_state, connection_id, protocol_version, now());
+
+        // Clients reconnecting pass the token of their session with ?session=<token>
+        let resumed = query_params.get("session").and_then(|session_id| {
+            let buffered = state::resume_session(&mut interface_state, session_id, connection_id);
+            if buffered.is_none() {
+                log(&format!(
+                    "Session {} is unknown, expired or still connected, starting a new one",
+                    session_id
+                ));
+            }
+            buffered.map(|buffered| (session_id.clone(), buffered))
+        });
+        let (session_id, buffered) = match resumed {
+            Some((session_id, buffered)) => {
+                log(&format!(
+                    "Connection {} resumed session {} with {} buffered messages",
+                    connection_id,
+                    session_id,
+                    buffered.len()
+                ));
+                (session_id, Some(buffered))
+            }
+            None => {
+                let session_id =
+                    generate_session_id(&interface_state.session_secret, connection_id);
+                state::start_session(&mut interface_state, session_id.clone(), connection_id);
+                (session_id, None)
+            }
+        };
+
+        let welcome_msg = match negotiated {
+            Ok(protocol_version) => {
+                create_welcome_message(protocol_version, &session_id, buffered.is_some())
+            }
+            Err(e) => create_error_message("", &e, "UNSUPPORTED_PROTOCOL_VERSION"),
+        };
+
+        if let Err(e) = send_to_connection(&interface_state, connection_id, &welcome_msg) {
+            log(&format!(
+                "Failed to send welcome to connection {}: {}",
+                connection_id, e
+            ));
+        }
+
+        // Replay what the session missed while it was offline
+        for message in buffered.unwrap_or_default() {
+            if let Err(e) = send_envelope_to_connection(&interface_state, connection_id, &message) {
+                log(&format!(
+                    "Failed to replay message to connection {}: {}",
+                    connection_id, e
+                ));
+            }
+        }
 
         // Serialize updated state
         let updated_state = match serde_json::to_vec(&interface_state) {
@@ -313,22 +567,36 @@ impl HttpHandlersGuest for Component {
             None => return Err("Missing state".to_string()),
         };
 
-        // Extract message content
-        let content = match message.ty {
-            MessageType::Text => {
-                String::from_utf8(message.text.expect("Text data is missing").into())
-                    .unwrap_or_default()
-            }
-            MessageType::Binary => {
-                String::from_utf8(message.data.expect("Binary data is missing")).unwrap_or_default()
+        state::touch_connection(&mut interface_state, connection_id, now());
+        sweep_idle_connections(&mut interface_state);
+
+        let response_messages = match message.ty {
+            // Keep-alive frames only count as activity
+            MessageType::Ping => vec![WebsocketMessage {
+                ty: MessageType::Pong,
+                text: None,
+                data: message.data,
+            }],
+            MessageType::Pong => Vec::new(),
+            _ => {
+                // Extract message content
+                let content = match message.ty {
+                    MessageType::Text => {
+                        String::from_utf8(message.text.expect("Text data is missing").into())
+                            .unwrap_or_default()
+                    }
+                    MessageType::Binary => {
+                        String::from_utf8(message.data.expect("Binary data is missing"))
+                            .unwrap_or_default()
+                    }
+                    _ => String::new(),
+                };
+
+                // Handle client message and get responses
+                handle_client_message(&mut interface_state, connection_id, &content)?
             }
-            _ => String::new(),
         };
 
-        // Handle client message and get responses
-        let response_messages =
-            handle_client_message(&mut interface_state, connection_id, &content)?;
-
         // Serialize updated state
         let updated_state = match serde_json::to_vec(&interface_state) {
             Ok(bytes) => bytes,
@@ -357,8 +625,7 @@ impl HttpHandlersGuest for Component {
             None => return Err("Missing state".to_string()),
         };
 
-        // Remove connection from state
-        remove_connection(&mut interface_state, connection_id);
+        drop_connection(&mut interface_state, connection_id, now());
 
         // Serialize updated state
         let updated_state = match serde_json::to_vec(&interface_state) {
@@ -427,7 +694,35 @@ impl MessageServerClient for Component {
     {
         log("Handling channel close message");
         log(&format!("Channel close message: {:?}", params));
-        Ok((state,))
+        let (channel_id,) = params;
+
+        // Parse state
+        let mut interface_state: InterfaceState = match state {
+            Some(bytes) => match serde_json::from_slice(&bytes) {
+                Ok(s) => s,
+                Err(e) => return Err(format!("Failed to parse state: {}", e)),
+            },
+            None => return Err("Missing state".to_string()),
+        };
+
+        // A titling actor that went away won't write its title
+        titling::end_titling_job(&mut interface_state, &channel_id);
+
+        // Drop the subscription so it gets reopened on the next streamed completion
+        if let Some(conversation_id) = interface_state.subscriptions.remove(&channel_id) {
+            log(&format!(
+                "Subscription for conversation {} closed",
+                conversation_id
+            ));
+        }
+
+        // Serialize updated state
+        let updated_state = match serde_json::to_vec(&interface_state) {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(format!("Failed to serialize state: {}", e)),
+        };
+
+        Ok((Some(updated_state),))
     }
 
     fn handle_channel_message(
@@ -439,9 +734,105 @@ impl MessageServerClient for Component {
     ) -> Result<(Option<bindings::exports::ntwk::theater::message_server_client::Json>,), String>
     {
         log("Received channel message");
-        log(&format!("Channel message: {:?}", params));
+        let (channel_id, data) = params;
+
+        // Parse state
+        let mut interface_state: InterfaceState = match state {
+            Some(bytes) => match serde_json::from_slice(&bytes) {
+                Ok(s) => s,
+                Err(e) => return Err(format!("Failed to parse state: {}", e)),
+            },
+            None => return Err("Missing state".to_string()),
+        };
+
+        // Errors are only logged, returning them would also throw away the state
+        // changes made so far, such as the end of a stream
+        match serde_json::from_slice::<ChatStateUpdate>(&data) {
+            Ok(update) => {
+                if interface_state.titling_jobs.contains_key(&channel_id) {
+                    titling::handle_titling_update(&mut interface_state, &channel_id, update);
+                } else if let Err(e) =
+                    streaming::handle_chat_state_update(&mut interface_state, &channel_id, update)
+                {
+                    log(&format!(
+                        "Failed to handle update on channel {}: {}",
+                        channel_id, e
+                    ));
+                }
+            }
+            Err(e) => log(&format!(
+                "Failed to parse update on channel {}: {}",
+                channel_id, e
+            )),
+        }
+        run_pending_titles(&mut interface_state);
+
+        // Serialize updated state
+        let updated_state = match serde_json::to_vec(&interface_state) {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(format!("Failed to serialize state: {}", e)),
+        };
+
+        Ok((Some(updated_state),))
+    }
+}
+
+impl ProcessHandlersGuest for Component {
+    fn handle_stdout(
+        state: Option<Vec<u8>>,
+        _params: (u64, Vec<u8>),
+    ) -> Result<(Option<Vec<u8>>,), String> {
+        Ok((state,))
+    }
+
+    fn handle_stderr(
+        state: Option<Vec<u8>>,
+        params: (u64, Vec<u8>),
+    ) -> Result<(Option<Vec<u8>>,), String> {
+        let (pid, data) = params;
+        log(&format!(
+            "Process {} stderr: {}",
+            pid,
+            String::from_utf8_lossy(&data)
+        ));
         Ok((state,))
     }
+
+    fn handle_exit(
+        state: Option<Vec<u8>>,
+        params: (u64, i32),
+    ) -> Result<(Option<Vec<u8>>,), String> {
+        let (pid, exit_code) = params;
+
+        // Parse state
+        let mut interface_state: InterfaceState = match state {
+            Some(bytes) => match serde_json::from_slice(&bytes) {
+                Ok(s) => s,
+                Err(e) => return Err(format!("Failed to parse state: {}", e)),
+            },
+            None => return Err("Missing state".to_string()),
+        };
+
+        // The sweep timer went off, sweep and set the next one
+        if interface_state.sweep_timer == Some(pid) {
+            if exit_code != 0 {
+                log(&format!("Sweep timer exited with code {}", exit_code));
+            }
+            interface_state.sweep_timer = None;
+            sweep_idle_connections(&mut interface_state);
+            start_sweep_timer(&mut interface_state);
+        } else {
+            log(&format!("Process {} exited with code {}", pid, exit_code));
+        }
+
+        // Serialize updated state
+        let updated_state = match serde_json::to_vec(&interface_state) {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(format!("Failed to serialize state: {}", e)),
+        };
+
+        Ok((Some(updated_state),))
+    }
 }
 
 impl SupervisorHandlersGuest for Component {
@@ -456,6 +847,14 @@ impl SupervisorHandlersGuest for Component {
     }
 }
 
+// Request ID of a message that isn't a valid envelope, so its error can still be
+// matched to it when the JSON itself was readable
+fn unparsed_request_id(content: &str) -> Option<String> {
+    serde_json::from_str::<serde_json::Value>(content)
+        .ok()
+        .and_then(|v| v.get("request_id")?.as_str().map(String::from))
+}
+
 // Handle client messages from WebSocket connections
 fn handle_client_message(
     interface_state: &mut InterfaceState,
@@ -464,31 +863,141 @@ fn handle_client_message(
 ) -> Result<Vec<WebsocketMessage>, String> {
     // Parse client message
     log(&format!("Parsing client message: {}", content));
-    let client_message: ClientMessage = match serde_json::from_str(content) {
-        Ok(msg) => msg,
+    let envelope: ClientEnvelope = match serde_json::from_str(content) {
+        Ok(envelope) => envelope,
         Err(e) => {
             log(&format!("Failed to parse client message: {}", e));
+            let request_id = unparsed_request_id(content);
             let error_msg =
                 create_error_message("", &format!("Invalid message format: {}", e), "PARSE_ERROR");
-            return Ok(vec![create_websocket_text_message(&error_msg)?]);
+            return Ok(vec![create_websocket_text_message(&ServerEnvelope::new(
+                request_id, error_msg,
+            ))?]);
+        }
+    };
+
+    let request_id = envelope.request_id;
+    let conversation_id = envelope
+        .message
+        .conversation_id()
+        .unwrap_or_default()
+        .to_string();
+    let replies = match handle_client_action(
+        interface_state,
+        connection_id,
+        request_id.as_deref(),
+        content,
+        envelope.message,
+    ) {
+        Ok(replies) => replies,
+        Err(e) => {
+            // Failures still get a reply the client can match to its request
+            log(&format!("Failed to handle client message: {}", e));
+            vec![create_error_message(&conversation_id, &e, "INTERNAL_ERROR")]
         }
     };
 
+    // Echo the request ID on every reply to this action
+    let replies = replies
+        .into_iter()
+        .map(|reply| create_websocket_text_message(&ServerEnvelope::new(request_id.clone(), reply)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let server_id = match interface_state.server_id {
+        Some(id) if !interface_state.pending_titles.is_empty() => id,
+        _ => return Ok(replies),
+    };
+
+    // Starting a title spawns and seeds an actor, the replies must not wait for it
+    for reply in &replies {
+        if let Err(e) = send_websocket_message(server_id, connection_id, reply) {
+            log(&format!(
+                "Failed to send reply to connection {}: {}",
+                connection_id, e
+            ));
+        }
+    }
+    run_pending_titles(interface_state);
+    Ok(Vec::new())
+}
+
+// Handle a single client action, returning the replies for the sender
+fn handle_client_action(
+    interface_state: &mut InterfaceState,
+    connection_id: u64,
+    request_id: Option<&str>,
+    content: &str,
+    client_message: ClientMessage,
+) -> Result<Vec<ServerMessage>, String> {
     // Handle different actions
     match client_message {
-        ClientMessage::NewConversation => {
+        ClientMessage::Ping => Ok(vec![ServerMessage::Pong { timestamp: now() }]),
+
+        ClientMessage::Hello { protocol_version } => {
+            match negotiate_protocol_version(Some(protocol_version)) {
+                Ok(version) => {
+                    set_protocol_version(interface_state, connection_id, version, now());
+                    let session_id = state::get_session_id(interface_state, connection_id);
+                    let resumed = state::is_resumed_connection(interface_state, connection_id);
+                    Ok(vec![create_welcome_message(version, &session_id, resumed)])
+                }
+                Err(e) => Ok(vec![create_error_message(
+                    "",
+                    &e,
+                    "UNSUPPORTED_PROTOCOL_VERSION",
+                )]),
+            }
+        }
+        ClientMessage::NewConversation(create) => {
+            // Explicit settings win over a preset, the default preset applies otherwise
+            let settings = match create.settings {
+                Some(settings) => Some(settings),
+                None => match state::resolve_preset(interface_state, create.preset.as_deref()) {
+                    Ok(settings) => settings,
+                    Err(e) => return Ok(vec![create_error_message("", &e, "PRESET_NOT_FOUND")]),
+                },
+            };
+            if let Some(invalid_msg) = settings
+                .as_ref()
+                .and_then(|s| check_settings(interface_state, "", s))
+            {
+                return Ok(vec![invalid_msg]);
+            }
+
             // Generate a new conversation ID
             let conversation_id = generate_conversation_id(content);
 
             // Start a new chat-state actor
-            let chat_state_actor_id =
-                start_chat_state_actor(&conversation_id, &interface_state.store_id)?;
+            let provider = settings.as_ref().map(|s| s.model_config.provider.clone());
+            let chat_state_actor_id = start_chat_state_actor(
+                &interface_state.chat_state_config,
+                &conversation_id,
+                &interface_state.store_id,
+                provider.as_deref(),
+            )?;
 
             log(&format!(
                 "Started chat-state actor for conversation {}: {}",
                 conversation_id, chat_state_actor_id
             ));
 
+            // Configure the actor before anyone can see the conversation
+            if let Err(e) =
+                configure_new_conversation(&chat_state_actor_id, settings, create.system_prompt)
+            {
+                if let Err(stop_err) = stop_child(&chat_state_actor_id) {
+                    log(&format!(
+                        "Failed to stop chat-state actor {}: {}",
+                        chat_state_actor_id, stop_err
+                    ));
+                }
+                return Ok(vec![create_error_message(
+                    &conversation_id,
+                    &format!("Error configuring conversation: {}", e),
+                    "CHAT_STATE_ERROR",
+                )]);
+            }
+
             // Associate connection with conversation
             set_active_conversation(
                 interface_state,
@@ -501,23 +1010,83 @@ fn handle_client_message(
             register_conversation_actor(
                 interface_state,
                 conversation_id.clone(),
-                chat_state_actor_id,
-                format!("Conversation {}", &conversation_id[..8]),
+                chat_state_actor_id.clone(),
+                titling::DEFAULT_TITLE.to_string(),
+                TitleSource::Default,
+                provider,
                 now(),
             );
 
+            // A title given up front counts as chosen by the user
+            if let Some(title) = create.title {
+                state::update_conversation_title(
+                    interface_state,
+                    &conversation_id,
+                    title,
+                    TitleSource::User,
+                )?;
+            }
+
             // save the updated state to the store
             store_state(interface_state)?;
 
+            broadcast_list_change(
+                interface_state,
+                ConversationListChange::Created,
+                &conversation_id,
+            );
+
             // Send confirmation to client
-            let response_msg = create_conversation_created_message(&conversation_id);
-            return Ok(vec![create_websocket_text_message(&response_msg)?]);
+            let mut replies = vec![create_conversation_created_message(&conversation_id)];
+
+            // Answer the first message as if it had been sent separately
+            if let Some(message) = create.message {
+                if let Some(busy_msg) =
+                    streaming::generation_in_progress(interface_state, &conversation_id)
+                {
+                    replies.push(busy_msg);
+                } else if create.stream {
+                    replies.push(streaming::start_streamed_completion(
+                        interface_state,
+                        connection_id,
+                        request_id,
+                        &conversation_id,
+                        &chat_state_actor_id,
+                        message,
+                    )?);
+                } else {
+                    match forward_to_chat_state(
+                        &chat_state_actor_id,
+                        &ChatStateRequest::AddMessage { message },
+                    )? {
+                        ChatStateResponse::Success => replies.extend(generate_completion_reply(
+                            interface_state,
+                            connection_id,
+                            &chat_state_actor_id,
+                            &conversation_id,
+                            None,
+                        )?),
+                        ChatStateResponse::Error { error } => replies.push(create_error_message(
+                            &conversation_id,
+                            &format!("Error from chat-state actor: {:?}", error),
+                            "CHAT_STATE_ERROR",
+                        )),
+                        _ => replies.push(create_error_message(
+                            &conversation_id,
+                            "Unexpected response from chat-state actor",
+                            "INTERNAL_ERROR",
+                        )),
+                    }
+                }
+            }
+
+            Ok(replies)
         }
         ClientMessage::SendMessage {
             conversation_id,
             message,
+            stream,
         } => {
-            // Get actor ID for this conversation
             let actor_id = match get_actor_id_for_conversation(interface_state, &conversation_id) {
                 Some(id) => id,
                 None => {
@@ -526,7 +1095,46 @@ fn handle_client_message(
                         "Conversation not found",
                         "CONVERSATION_NOT_FOUND",
                     );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    return Ok(vec![error_msg]);
+                }
+            };
+
+            // A plain reply must not write to a chain a streamed one is still writing
+            if let Some(busy_msg) =
+                streaming::generation_in_progress(interface_state, &conversation_id)
+            {
+                return Ok(vec![busy_msg]);
+            }
+
+            // This connection now views the conversation and gets its updates
+            set_active_conversation(
+                interface_state,
+                connection_id,
+                conversation_id.clone(),
+                now(),
+            );
+
+            if stream {
+                let response_msg = streaming::start_streamed_completion(
+                    interface_state,
+                    connection_id,
+                    request_id,
+                    &conversation_id,
+                    &actor_id,
+                    message,
+                )?;
+                return Ok(vec![response_msg]);
+            }
+
+            // Remember where the chain ended so only the new messages are sent back
+            let base_head = match chain::get_head(&actor_id) {
+                Ok(head) => head,
+                Err(e) => {
+                    return Ok(vec![create_error_message(
+                        &conversation_id,
+                        &e,
+                        "HEAD_ERROR",
+                    )]);
                 }
             };
 
@@ -536,92 +1144,35 @@ fn handle_client_message(
             let response = forward_to_chat_state(&actor_id, &chat_state_msg)?;
 
             match response {
-                ChatStateResponse::Success => {
-                    let completion_response =
-                        forward_to_chat_state(&actor_id, &ChatStateRequest::GenerateCompletion)?;
-
-                    match completion_response {
-                        // In the new chain-driven architecture, GenerateCompletion returns a Head response
-                        // with the ID of the latest message in the chain
-                        ChatStateResponse::Head { head: _ } => {
-                            // Now request the full updated conversation history
-                            let history_response =
-                                forward_to_chat_state(&actor_id, &ChatStateRequest::GetHistory)?;
-
-                            match history_response {
-                                ChatStateResponse::History { messages } => {
-                                    // Convert ChatMessage objects to Message objects for client compatibility
-                                    let client_messages: Vec<Message> =
-                                        messages.iter().map(|m| m.message.clone()).collect();
-
-                                    let response_msg =
-                                        create_messages_response(&conversation_id, client_messages);
-                                    return Ok(vec![create_websocket_text_message(&response_msg)?]);
-                                }
-                                ChatStateResponse::Error { error } => {
-                                    let error_msg = create_error_message(
-                                        &conversation_id,
-                                        &format!("Error from chat-state actor: {:?}", error),
-                                        "CHAT_STATE_ERROR",
-                                    );
-                                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
-                                }
-                                _ => {
-                                    let error_msg = create_error_message(
-                                        &conversation_id,
-                                        "Unexpected response when retrieving message history",
-                                        "INTERNAL_ERROR",
-                                    );
-                                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
-                                }
-                            }
-                        }
-                        // Handle the old completion response format for backwards compatibility
-                        ChatStateResponse::Completion { messages } => {
-                            let response_msg = create_messages_response(&conversation_id, messages);
-                            return Ok(vec![create_websocket_text_message(&response_msg)?]);
-                        }
-                        ChatStateResponse::Error { error } => {
-                            let error_msg = create_error_message(
-                                &conversation_id,
-                                &format!("Error from chat-state actor: {:?}", error),
-                                "CHAT_STATE_ERROR",
-                            );
-                            return Ok(vec![create_websocket_text_message(&error_msg)?]);
-                        }
-                        _ => {
-                            let error_msg = create_error_message(
-                                &conversation_id,
-                                "Unexpected response from chat-state actor",
-                                "INTERNAL_ERROR",
-                            );
-                            return Ok(vec![create_websocket_text_message(&error_msg)?]);
-                        }
-                    }
-                }
+                ChatStateResponse::Success => generate_completion_reply(
+                    interface_state,
+                    connection_id,
+                    &actor_id,
+                    &conversation_id,
+                    base_head,
+                ),
                 _ => {
                     let error_msg = create_error_message(
                         &conversation_id,
                         "Unexpected response from chat-state actor",
                         "INTERNAL_ERROR",
                     );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    return Ok(vec![error_msg]);
                 }
             }
         }
-        ClientMessage::ListConversations => {
+        ClientMessage::ListConversations { include_archived } => {
             let response = ServerMessage::ConversationList {
-                conversations: interface_state.conversation_metadata.clone(),
+                conversations: state::list_conversations(interface_state, include_archived),
             };
 
-            return Ok(vec![create_websocket_text_message(&response)?]);
+            return Ok(vec![response]);
         }
         ClientMessage::GetSettings { conversation_id } => {
             log(&format!(
                 "Getting settings for conversation: {}",
                 conversation_id
             ));
-            // Get actor ID for this conversation
             let actor_id = match get_actor_id_for_conversation(interface_state, &conversation_id) {
                 Some(id) => id,
                 None => {
@@ -630,7 +1181,7 @@ fn handle_client_message(
                         "Conversation not found",
                         "CONVERSATION_NOT_FOUND",
                     );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    return Ok(vec![error_msg]);
                 }
             };
 
@@ -650,7 +1201,7 @@ fn handle_client_message(
                         conversation_id
                     ));
                     let response_msg = create_settings_response(&conversation_id, settings);
-                    return Ok(vec![create_websocket_text_message(&response_msg)?]);
+                    return Ok(vec![response_msg]);
                 }
                 ChatStateResponse::Error { error } => {
                     log(&format!(
@@ -662,7 +1213,7 @@ fn handle_client_message(
                         &format!("Error from chat-state actor: {:?}", error),
                         "CHAT_STATE_ERROR",
                     );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    return Ok(vec![error_msg]);
                 }
                 _ => {
                     log(&format!(
@@ -674,7 +1225,7 @@ fn handle_client_message(
                         "Unexpected response from chat-state actor",
                         "INTERNAL_ERROR",
                     );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    return Ok(vec![error_msg]);
                 }
             }
         }
@@ -682,7 +1233,6 @@ fn handle_client_message(
             conversation_id,
             settings,
         } => {
-            // Get actor ID for this conversation
             let actor_id = match get_actor_id_for_conversation(interface_state, &conversation_id) {
                 Some(id) => id,
                 None => {
@@ -691,17 +1241,33 @@ fn handle_client_message(
                         "Conversation not found",
                         "CONVERSATION_NOT_FOUND",
                     );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    return Ok(vec![error_msg]);
                 }
             };
 
+            if let Some(invalid_msg) = check_settings(interface_state, &conversation_id, &settings)
+            {
+                return Ok(vec![invalid_msg]);
+            }
+
             // Forward request to chat-state actor
-            let chat_state_msg = ChatStateRequest::UpdateSettings { settings };
+            let chat_state_msg = ChatStateRequest::UpdateSettings {
+                settings: settings.clone(),
+            };
             let response = forward_to_chat_state(&actor_id, &chat_state_msg)?;
             match response {
                 ChatStateResponse::Success => {
+                    // Keep the other viewers' settings panels in sync
+                    let settings_msg = create_settings_response(&conversation_id, settings);
+                    broadcast_to_conversation(
+                        interface_state,
+                        &conversation_id,
+                        &settings_msg,
+                        Some(connection_id),
+                    );
+
                     let success_msg = create_success_response();
-                    return Ok(vec![create_websocket_text_message(&success_msg)?]);
+                    return Ok(vec![success_msg]);
                 }
                 ChatStateResponse::Error { error } => {
                     let error_msg = create_error_message(
@@ -709,7 +1275,7 @@ fn handle_client_message(
                         &format!("Error from chat-state actor: {:?}", error),
                         "CHAT_STATE_ERROR",
                     );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    return Ok(vec![error_msg]);
                 }
                 _ => {
                     let error_msg = create_error_message(
@@ -717,12 +1283,11 @@ fn handle_client_message(
                         "Unexpected response from chat-state actor",
                         "INTERNAL_ERROR",
                     );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    return Ok(vec![error_msg]);
                 }
             }
         }
         ClientMessage::GetConversation { conversation_id } => {
-            // Get actor ID for this conversation
             let actor_id = match get_actor_id_for_conversation(interface_state, &conversation_id) {
                 Some(id) => id,
                 None => {
@@ -731,10 +1296,18 @@ fn handle_client_message(
                         "Conversation not found",
                         "CONVERSATION_NOT_FOUND",
                     );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    return Ok(vec![error_msg]);
                 }
             };
 
+            // This connection now views the conversation and gets its updates
+            set_active_conversation(
+                interface_state,
+                connection_id,
+                conversation_id.clone(),
+                now(),
+            );
+
             // Request the head ID first to confirm we have a valid conversation
             let head_response = forward_to_chat_state(&actor_id, &ChatStateRequest::GetHead)?;
 
@@ -752,7 +1325,7 @@ fn handle_client_message(
 
                             let response_msg =
                                 create_conversation_response(&conversation_id, client_messages);
-                            return Ok(vec![create_websocket_text_message(&response_msg)?]);
+                            return Ok(vec![response_msg]);
                         }
                         ChatStateResponse::Error { error } => {
                             let error_msg = create_error_message(
@@ -760,7 +1333,7 @@ fn handle_client_message(
                                 &format!("Error from chat-state actor: {:?}", error),
                                 "CHAT_STATE_ERROR",
                             );
-                            return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                            return Ok(vec![error_msg]);
                         }
                         _ => {
                             let error_msg = create_error_message(
@@ -768,7 +1341,7 @@ fn handle_client_message(
                                 "Unexpected response when retrieving message history",
                                 "INTERNAL_ERROR",
                             );
-                            return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                            return Ok(vec![error_msg]);
                         }
                     }
                 }
@@ -778,7 +1351,7 @@ fn handle_client_message(
                         &format!("Error from chat-state actor: {:?}", error),
                         "CHAT_STATE_ERROR",
                     );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    return Ok(vec![error_msg]);
                 }
                 _ => {
                     let error_msg = create_error_message(
@@ -786,160 +1359,1707 @@ fn handle_client_message(
                         "Unexpected response from chat-state actor",
                         "INTERNAL_ERROR",
                     );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    return Ok(vec![error_msg]);
                 }
             }
         }
 
-        ClientMessage::GetMessageById {
+        ClientMessage::DeleteConversation { conversation_id } => {
+            if delete_conversation(interface_state, &conversation_id, Some(connection_id))? {
+                Ok(vec![create_conversation_deleted_message(&conversation_id)])
+            } else {
+                Ok(vec![create_error_message(
+                    &conversation_id,
+                    "Conversation not found",
+                    "CONVERSATION_NOT_FOUND",
+                )])
+            }
+        }
+
+        ClientMessage::ArchiveConversation { conversation_id } => {
+            if !state::set_conversation_archived(interface_state, &conversation_id, true, now()) {
+                return Ok(vec![create_error_message(
+                    &conversation_id,
+                    "Conversation not found",
+                    "CONVERSATION_NOT_FOUND",
+                )]);
+            }
+
+            stop_conversation_actor(interface_state, &conversation_id);
+            store_state(interface_state)?;
+
+            broadcast_list_change(
+                interface_state,
+                ConversationListChange::Archived,
+                &conversation_id,
+            );
+
+            Ok(vec![create_conversation_archived_message(
+                &conversation_id,
+                true,
+            )])
+        }
+
+        ClientMessage::UnarchiveConversation { conversation_id } => {
+            if !state::set_conversation_archived(interface_state, &conversation_id, false, now()) {
+                return Ok(vec![create_error_message(
+                    &conversation_id,
+                    "Conversation not found",
+                    "CONVERSATION_NOT_FOUND",
+                )]);
+            }
+
+            // Reopen the conversation with a fresh chat-state actor
+            if get_actor_id_for_conversation(interface_state, &conversation_id).is_none() {
+                let provider = interface_state.conversation_metadata[&conversation_id]
+                    .provider
+                    .clone();
+                let chat_state_actor_id = start_chat_state_actor(
+                    &interface_state.chat_state_config,
+                    &conversation_id,
+                    &interface_state.store_id,
+                    provider.as_deref(),
+                )?;
+                interface_state
+                    .conversation_actors
+                    .insert(conversation_id.clone(), chat_state_actor_id);
+            }
+            store_state(interface_state)?;
+
+            broadcast_list_change(
+                interface_state,
+                ConversationListChange::Unarchived,
+                &conversation_id,
+            );
+
+            Ok(vec![create_conversation_archived_message(
+                &conversation_id,
+                false,
+            )])
+        }
+
+        ClientMessage::ForkConversation {
+            conversation_id,
+            message_id,
+        } => {
+            // Get actor ID for the source conversation
+            let source_actor_id =
+                match get_actor_id_for_conversation(interface_state, &conversation_id) {
+                    Some(id) => id,
+                    None => {
+                        return Ok(vec![create_error_message(
+                            &conversation_id,
+                            "Conversation not found",
+                            "CONVERSATION_NOT_FOUND",
+                        )]);
+                    }
+                };
+
+            let ancestry = match chain::get_ancestry(&source_actor_id, &message_id) {
+                Ok(messages) => messages,
+                Err(e) => {
+                    return Ok(vec![create_error_message(
+                        &conversation_id,
+                        &e,
+                        "MESSAGE_ERROR",
+                    )]);
+                }
+            };
+
+            let settings =
+                match forward_to_chat_state(&source_actor_id, &ChatStateRequest::GetSettings)? {
+                    ChatStateResponse::Settings { settings } => settings,
+                    ChatStateResponse::Error { error } => {
+                        return Ok(vec![create_error_message(
+                            &conversation_id,
+                            &format!("Error from chat-state actor: {:?}", error),
+                            "CHAT_STATE_ERROR",
+                        )]);
+                    }
+                    _ => {
+                        return Ok(vec![create_error_message(
+                            &conversation_id,
+                            "Unexpected response from chat-state actor",
+                            "INTERNAL_ERROR",
+                        )]);
+                    }
+                };
+
+            // Start the fork with the source settings and replay the chain into it
+            let fork_id = generate_conversation_id(content);
+            let provider = settings.model_config.provider.clone();
+            let fork_actor_id = start_chat_state_actor(
+                &interface_state.chat_state_config,
+                &fork_id,
+                &interface_state.store_id,
+                Some(&provider),
+            )?;
+            log(&format!(
+                "Started chat-state actor for fork {} of {}: {}",
+                fork_id, conversation_id, fork_actor_id
+            ));
+
+            let seed_messages: Vec<Message> = ancestry.into_iter().map(|m| m.message).collect();
+            let message_count = seed_messages.len() as u32;
+            let last_message = seed_messages.last().cloned();
+            if let Err(e) = seed_chat_state_actor(&fork_actor_id, Some(settings), seed_messages) {
+                if let Err(stop_err) = stop_child(&fork_actor_id) {
+                    log(&format!(
+                        "Failed to stop chat-state actor {}: {}",
+                        fork_actor_id, stop_err
+                    ));
+                }
+                return Ok(vec![create_error_message(
+                    &conversation_id,
+                    &format!("Error copying conversation into fork: {}", e),
+                    "CHAT_STATE_ERROR",
+                )]);
+            }
+
+            let source_title = interface_state
+                .conversation_metadata
+                .get(&conversation_id)
+                .map(|meta| meta.title.clone())
+                .unwrap_or_default();
+            register_conversation_actor(
+                interface_state,
+                fork_id.clone(),
+                fork_actor_id,
+                format!("Fork of {}", source_title),
+                // Names its origin, so it is kept like a title the user chose
+                TitleSource::User,
+                Some(provider),
+                now(),
+            );
+            state::set_fork_origin(
+                interface_state,
+                &fork_id,
+                ForkOrigin {
+                    conversation_id: conversation_id.clone(),
+                    message_id,
+                },
+            );
+            state::update_conversation_metadata(
+                interface_state,
+                &fork_id,
+                message_count,
+                last_message.as_ref(),
+                now(),
+            );
+            set_active_conversation(interface_state, connection_id, fork_id.clone(), now());
+
+            store_state(interface_state)?;
+
+            broadcast_list_change(interface_state, ConversationListChange::Created, &fork_id);
+
+            let metadata = interface_state.conversation_metadata[&fork_id].clone();
+            Ok(vec![ServerMessage::ConversationForked {
+                conversation_id: fork_id,
+                metadata,
+            }])
+        }
+
+        ClientMessage::EditMessage {
             conversation_id,
             message_id,
+            message,
+            stream,
         } => {
-            // Get actor ID for this conversation
             let actor_id = match get_actor_id_for_conversation(interface_state, &conversation_id) {
                 Some(id) => id,
                 None => {
-                    let error_msg = create_error_message(
+                    return Ok(vec![create_error_message(
                         &conversation_id,
                         "Conversation not found",
                         "CONVERSATION_NOT_FOUND",
-                    );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    )]);
                 }
             };
 
-            // Forward request to chat-state actor
-            let chat_state_msg = ChatStateRequest::GetMessage { message_id };
-            let response = forward_to_chat_state(&actor_id, &chat_state_msg)?;
+            // This connection now views the conversation and gets its updates
+            set_active_conversation(
+                interface_state,
+                connection_id,
+                conversation_id.clone(),
+                now(),
+            );
 
-            match response {
-                ChatStateResponse::ChatMessage { message } => {
-                    let response_msg = create_message_by_id_response(&conversation_id, message);
-                    return Ok(vec![create_websocket_text_message(&response_msg)?]);
-                }
-                ChatStateResponse::Error { error } => {
-                    let error_msg = create_error_message(
+            let original = match chain::get_message(&actor_id, &message_id) {
+                Ok(original) => original,
+                Err(e) => {
+                    return Ok(vec![create_error_message(
                         &conversation_id,
-                        &format!("Error retrieving message: {:?}", error),
+                        &e,
                         "MESSAGE_ERROR",
-                    );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    )]);
                 }
-                _ => {
-                    let error_msg = create_error_message(
+            };
+
+            if original.message.role != "user" {
+                return Ok(vec![create_error_message(
+                    &conversation_id,
+                    "Only user messages can be edited",
+                    "INVALID_MESSAGE",
+                )]);
+            }
+
+            if let Some(busy_msg) =
+                streaming::generation_in_progress(interface_state, &conversation_id)
+            {
+                return Ok(vec![busy_msg]);
+            }
+
+            // Branch off next to the original, the old branch stays in the chain
+            // and its tip is remembered so it can be found again
+            let previous_head = match chain::get_head(&actor_id) {
+                Ok(head) => head,
+                Err(e) => {
+                    return Ok(vec![create_error_message(
                         &conversation_id,
-                        "Unexpected response when retrieving message",
+                        &e,
+                        "HEAD_ERROR",
+                    )]);
+                }
+            };
+            if let Some(head) = &previous_head {
+                if state::record_head(interface_state, &conversation_id, head) {
+                    state::store_branches(interface_state)?;
+                }
+            }
+            if let Err(e) = chain::set_head(&actor_id, original.parent_id.clone()) {
+                return Ok(vec![create_error_message(
+                    &conversation_id,
+                    &e,
+                    "HEAD_ERROR",
+                )]);
+            }
+
+            let result = if stream {
+                streaming::start_streamed_completion(
+                    interface_state,
+                    connection_id,
+                    request_id,
+                    &conversation_id,
+                    &actor_id,
+                    message,
+                )
+                .map(|response_msg| vec![response_msg])
+            } else {
+                match forward_to_chat_state(&actor_id, &ChatStateRequest::AddMessage { message }) {
+                    Ok(ChatStateResponse::Success) => generate_completion_reply(
+                        interface_state,
+                        connection_id,
+                        &actor_id,
+                        &conversation_id,
+                        original.parent_id,
+                    ),
+                    Ok(ChatStateResponse::Error { error }) => Ok(vec![create_error_message(
+                        &conversation_id,
+                        &format!("Error from chat-state actor: {:?}", error),
+                        "CHAT_STATE_ERROR",
+                    )]),
+                    Ok(_) => Ok(vec![create_error_message(
+                        &conversation_id,
+                        "Unexpected response from chat-state actor",
                         "INTERNAL_ERROR",
-                    );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    )]),
+                    Err(e) => Err(e),
                 }
+            };
+
+            // A failed edit must not leave the conversation on the half-made branch
+            let failed = match &result {
+                Ok(replies) => replies
+                    .iter()
+                    .any(|reply| matches!(reply, ServerMessage::Error { .. })),
+                Err(_) => true,
+            };
+            if failed {
+                restore_head(&actor_id, &conversation_id, previous_head);
+                refresh_conversation_metadata(interface_state, &conversation_id, &actor_id);
+            } else if let Some(stream) = interface_state.active_streams.get_mut(&conversation_id) {
+                stream.restore_head = Some(previous_head);
             }
+            result
         }
 
-        ClientMessage::GetHeadId { conversation_id } => {
-            // Get actor ID for this conversation
+        ClientMessage::Regenerate {
+            conversation_id,
+            model_config,
+            temperature,
+        } => {
             let actor_id = match get_actor_id_for_conversation(interface_state, &conversation_id) {
                 Some(id) => id,
                 None => {
-                    let error_msg = create_error_message(
+                    return Ok(vec![create_error_message(
                         &conversation_id,
                         "Conversation not found",
                         "CONVERSATION_NOT_FOUND",
-                    );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    )]);
                 }
             };
 
-            // Forward request to chat-state actor
-            let chat_state_msg = ChatStateRequest::GetHead;
-            let response = forward_to_chat_state(&actor_id, &chat_state_msg)?;
+            if let Some(busy_msg) =
+                streaming::generation_in_progress(interface_state, &conversation_id)
+            {
+                return Ok(vec![busy_msg]);
+            }
 
-            match response {
-                ChatStateResponse::Head { head } => {
-                    let response_msg = create_head_id_response(&conversation_id, &head);
-                    return Ok(vec![create_websocket_text_message(&response_msg)?]);
+            let previous = match forward_to_chat_state(&actor_id, &ChatStateRequest::GetHead)? {
+                ChatStateResponse::Head { head: Some(head) } => {
+                    match chain::get_message(&actor_id, &head) {
+                        Ok(message) => message,
+                        Err(e) => {
+                            return Ok(vec![create_error_message(
+                                &conversation_id,
+                                &e,
+                                "MESSAGE_ERROR",
+                            )]);
+                        }
+                    }
+                }
+                ChatStateResponse::Head { head: None } => {
+                    return Ok(vec![create_error_message(
+                        &conversation_id,
+                        "Conversation has no messages to regenerate",
+                        "INVALID_MESSAGE",
+                    )]);
                 }
                 ChatStateResponse::Error { error } => {
-                    let error_msg = create_error_message(
+                    return Ok(vec![create_error_message(
                         &conversation_id,
                         &format!("Error retrieving head: {:?}", error),
                         "HEAD_ERROR",
-                    );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    )]);
                 }
                 _ => {
-                    let error_msg = create_error_message(
+                    return Ok(vec![create_error_message(
                         &conversation_id,
                         "Unexpected response when retrieving head",
                         "INTERNAL_ERROR",
-                    );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    )]);
                 }
-            }
-        }
-        ClientMessage::RenameConversation { conversation_id, new_title } => {
-            // Check if the conversation exists
-            if !interface_state.conversation_metadata.contains_key(&conversation_id) {
-                let error_msg = create_error_message(
+            };
+
+            if previous.message.role != "assistant" {
+                return Ok(vec![create_error_message(
                     &conversation_id,
-                    "Conversation not found",
-                    "CONVERSATION_NOT_FOUND",
-                );
-                return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                    "The current head is not an assistant message",
+                    "INVALID_MESSAGE",
+                )]);
             }
 
-            // Update the conversation title
-            match state::update_conversation_title(interface_state, &conversation_id, new_title.clone()) {
+            // Apply one-off overrides, remembering the settings to restore afterwards
+            let original_settings = if model_config.is_some() || temperature.is_some() {
+                let settings =
+                    match forward_to_chat_state(&actor_id, &ChatStateRequest::GetSettings)? {
+                        ChatStateResponse::Settings { settings } => settings,
+                        _ => {
+                            return Ok(vec![create_error_message(
+                                &conversation_id,
+                                "Unexpected response when retrieving settings",
+                                "INTERNAL_ERROR",
+                            )]);
+                        }
+                    };
+
+                let mut overridden = settings.clone();
+                if let Some(model_config) = model_config {
+                    overridden.model_config = model_config;
+                }
+                if temperature.is_some() {
+                    overridden.temperature = temperature;
+                }
+                if let Some(invalid_msg) =
+                    check_settings(interface_state, &conversation_id, &overridden)
+                {
+                    return Ok(vec![invalid_msg]);
+                }
+                match forward_to_chat_state(
+                    &actor_id,
+                    &ChatStateRequest::UpdateSettings {
+                        settings: overridden,
+                    },
+                )? {
+                    ChatStateResponse::Success => {}
+                    ChatStateResponse::Error { error } => {
+                        return Ok(vec![create_error_message(
+                            &conversation_id,
+                            &format!("Error from chat-state actor: {:?}", error),
+                            "CHAT_STATE_ERROR",
+                        )]);
+                    }
+                    _ => {
+                        return Ok(vec![create_error_message(
+                            &conversation_id,
+                            "Unexpected response when updating settings",
+                            "INTERNAL_ERROR",
+                        )]);
+                    }
+                }
+
+                Some(settings)
+            } else {
+                None
+            };
+
+            // Branch off next to the previous reply and generate a new one
+            let completion =
+                chain::set_head(&actor_id, previous.parent_id.clone()).and_then(|_| {
+                    forward_to_chat_state(&actor_id, &ChatStateRequest::GenerateCompletion)
+                });
+
+            // The overrides only apply to this one reply, whatever happened to it
+            if let Some(settings) = original_settings {
+                match forward_to_chat_state(
+                    &actor_id,
+                    &ChatStateRequest::UpdateSettings { settings },
+                ) {
+                    Ok(ChatStateResponse::Success) => {}
+                    Ok(response) => log(&format!(
+                        "Unexpected response when restoring settings of {}: {:?}",
+                        conversation_id, response
+                    )),
+                    Err(e) => log(&format!(
+                        "Failed to restore settings of {}: {}",
+                        conversation_id, e
+                    )),
+                }
+            }
+
+            let head = match completion {
+                Ok(ChatStateResponse::Head { head: Some(head) }) => head,
+                Ok(ChatStateResponse::Error { error }) => {
+                    restore_head(&actor_id, &conversation_id, previous.id.clone());
+                    return Ok(vec![create_error_message(
+                        &conversation_id,
+                        &format!("Error from chat-state actor: {:?}", error),
+                        "CHAT_STATE_ERROR",
+                    )]);
+                }
                 Ok(_) => {
-                    // Create a response confirming the title was updated
-                    let response_msg = protocol::create_conversation_renamed_message(&conversation_id, &new_title);
-                    return Ok(vec![create_websocket_text_message(&response_msg)?]);
+                    restore_head(&actor_id, &conversation_id, previous.id.clone());
+                    return Ok(vec![create_error_message(
+                        &conversation_id,
+                        "Unexpected response from chat-state actor",
+                        "INTERNAL_ERROR",
+                    )]);
                 }
                 Err(e) => {
-                    let error_msg = create_error_message(
+                    restore_head(&actor_id, &conversation_id, previous.id.clone());
+                    return Ok(vec![create_error_message(
                         &conversation_id,
-                        &format!("Error updating title: {}", e),
-                        "UPDATE_ERROR",
-                    );
-                    return Ok(vec![create_websocket_text_message(&error_msg)?]);
+                        &e,
+                        "HEAD_ERROR",
+                    )]);
+                }
+            };
+
+            if let Some(previous_id) = &previous.id {
+                state::record_head(interface_state, &conversation_id, previous_id);
+            }
+            state::record_head(interface_state, &conversation_id, &head);
+            state::store_branches(interface_state)?;
+            refresh_conversation_metadata(interface_state, &conversation_id, &actor_id);
+
+            // Every reply to the same message is an alternative, including older regenerations
+            let nodes = match get_branch_tree(interface_state, &conversation_id, &actor_id) {
+                Ok((nodes, _)) => nodes,
+                Err(e) => {
+                    return Ok(vec![create_error_message(
+                        &conversation_id,
+                        &e,
+                        "MESSAGE_ERROR",
+                    )]);
+                }
+            };
+            let mut alternatives = Vec::new();
+            for node in nodes
+                .iter()
+                .filter(|node| node.parent_id == previous.parent_id)
+            {
+                match chain::get_message(&actor_id, &node.id) {
+                    Ok(message) => alternatives.push(message),
+                    Err(e) => {
+                        return Ok(vec![create_error_message(
+                            &conversation_id,
+                            &e,
+                            "MESSAGE_ERROR",
+                        )]);
+                    }
                 }
             }
+            state::flag_truncated(interface_state, &conversation_id, &mut alternatives);
+            let selected = alternatives
+                .iter()
+                .position(|message| message.id.as_deref() == Some(head.as_str()))
+                .unwrap_or_default();
+
+            let response_msg = ServerMessage::Regenerated {
+                conversation_id: conversation_id.clone(),
+                alternatives,
+                selected,
+            };
+            broadcast_to_conversation(
+                interface_state,
+                &conversation_id,
+                &response_msg,
+                Some(connection_id),
+            );
+            Ok(vec![response_msg])
         }
-    }
-}
 
-// Start a new chat-state actor for a conversation
-fn start_chat_state_actor(conversation_id: &str, store_id: &str) -> Result<String, String> {
-    // Chat-state actor manifest path
-    let manifest_path = "/Users/colinrozzi/work/actor-registry/chat-state/manifest.toml";
+        ClientMessage::GetChildren {
+            conversation_id,
+            message_id,
+        } => {
+            let actor_id = match get_actor_id_for_conversation(interface_state, &conversation_id) {
+                Some(id) => id,
+                None => {
+                    return Ok(vec![create_error_message(
+                        &conversation_id,
+                        "Conversation not found",
+                        "CONVERSATION_NOT_FOUND",
+                    )]);
+                }
+            };
 
-    // Prepare initial state (serialized as JSON)
-    let initial_state = serde_json::json!({
-        "conversation_id": conversation_id,
-        "store_id": store_id,
-    });
+            let nodes = match get_branch_tree(interface_state, &conversation_id, &actor_id) {
+                Ok((nodes, _)) => nodes,
+                Err(e) => {
+                    return Ok(vec![create_error_message(
+                        &conversation_id,
+                        &e,
+                        "MESSAGE_ERROR",
+                    )]);
+                }
+            };
 
-    // Spawn the actor
-    let actor_id = spawn(manifest_path, Some(initial_state.to_string().as_bytes()))?;
-    log(&format!("Spawned chat-state actor with ID: {}", actor_id));
+            let children = nodes
+                .iter()
+                .filter(|node| node.parent_id.as_deref() == Some(message_id.as_str()))
+                .cloned()
+                .collect();
+
+            Ok(vec![ServerMessage::Children {
+                conversation_id,
+                message_id,
+                children,
+            }])
+        }
 
-    Ok(actor_id)
-}
+        ClientMessage::GetBranches { conversation_id } => {
+            let actor_id = match get_actor_id_for_conversation(interface_state, &conversation_id) {
+                Some(id) => id,
+                None => {
+                    return Ok(vec![create_error_message(
+                        &conversation_id,
+                        "Conversation not found",
+                        "CONVERSATION_NOT_FOUND",
+                    )]);
+                }
+            };
 
-// Forward a message to a chat-state actor
-fn forward_to_chat_state(
-    actor_id: &str,
-    req: &ChatStateRequest,
-) -> Result<ChatStateResponse, String> {
-    // Serialize the message
-    let req_bytes = match serde_json::to_vec(req) {
-        Ok(bytes) => bytes,
-        Err(e) => return Err(format!("Failed to serialize message: {}", e)),
-    };
+            match get_branch_tree(interface_state, &conversation_id, &actor_id) {
+                Ok((nodes, head_id)) => {
+                    let leaves = nodes
+                        .iter()
+                        .filter(|node| node.children.is_empty())
+                        .map(|node| node.id.clone())
+                        .collect();
+
+                    Ok(vec![ServerMessage::Branches {
+                        conversation_id,
+                        nodes,
+                        leaves,
+                        head_id,
+                    }])
+                }
+                Err(e) => Ok(vec![create_error_message(
+                    &conversation_id,
+                    &e,
+                    "MESSAGE_ERROR",
+                )]),
+            }
+        }
 
-    // Send request to chat-state actor
-    let response_bytes = match request(actor_id, &req_bytes) {
-        Ok(response) => response,
+        ClientMessage::SwitchBranch {
+            conversation_id,
+            leaf_id,
+        } => {
+            let actor_id = match get_actor_id_for_conversation(interface_state, &conversation_id) {
+                Some(id) => id,
+                None => {
+                    return Ok(vec![create_error_message(
+                        &conversation_id,
+                        "Conversation not found",
+                        "CONVERSATION_NOT_FOUND",
+                    )]);
+                }
+            };
+
+            if let Some(busy_msg) =
+                streaming::generation_in_progress(interface_state, &conversation_id)
+            {
+                return Ok(vec![busy_msg]);
+            }
+
+            // Only branch tips can be switched to. A message missing from the tree
+            // may still be the tip of a branch made before heads were remembered,
+            // it is accepted if it belongs to this conversation's chain.
+            let nodes = match get_branch_tree(interface_state, &conversation_id, &actor_id) {
+                Ok((nodes, _)) => nodes,
+                Err(e) => {
+                    return Ok(vec![create_error_message(
+                        &conversation_id,
+                        &e,
+                        "MESSAGE_ERROR",
+                    )]);
+                }
+            };
+            if nodes
+                .iter()
+                .any(|node| node.id == leaf_id && !node.children.is_empty())
+            {
+                return Ok(vec![create_error_message(
+                    &conversation_id,
+                    "Message is not the tip of a branch",
+                    "INVALID_BRANCH",
+                )]);
+            }
+
+            // Remember where we are leaving from before moving
+            if let Ok(Some(head)) = chain::get_head(&actor_id) {
+                state::record_head(interface_state, &conversation_id, &head);
+            }
+
+            let mut messages = match chain::get_ancestry(&actor_id, &leaf_id) {
+                Ok(messages) => messages,
+                Err(e) => {
+                    return Ok(vec![create_error_message(
+                        &conversation_id,
+                        &e,
+                        "MESSAGE_ERROR",
+                    )]);
+                }
+            };
+
+            if let Err(e) = chain::set_head(&actor_id, Some(leaf_id.clone())) {
+                return Ok(vec![create_error_message(
+                    &conversation_id,
+                    &e,
+                    "HEAD_ERROR",
+                )]);
+            }
+            state::record_head(interface_state, &conversation_id, &leaf_id);
+            state::store_branches(interface_state)?;
+            refresh_conversation_metadata(interface_state, &conversation_id, &actor_id);
+
+            state::flag_truncated(interface_state, &conversation_id, &mut messages);
+            let response_msg = ServerMessage::BranchSwitched {
+                conversation_id: conversation_id.clone(),
+                head_id: leaf_id,
+                messages,
+            };
+            broadcast_to_conversation(
+                interface_state,
+                &conversation_id,
+                &response_msg,
+                Some(connection_id),
+            );
+            Ok(vec![response_msg])
+        }
+
+        ClientMessage::CancelGeneration { conversation_id } => {
+            let actor_id = match get_actor_id_for_conversation(interface_state, &conversation_id) {
+                Some(id) => id,
+                None => {
+                    return Ok(vec![create_error_message(
+                        &conversation_id,
+                        "Conversation not found",
+                        "CONVERSATION_NOT_FOUND",
+                    )]);
+                }
+            };
+
+            let response_msg = streaming::cancel_streamed_completion(
+                interface_state,
+                &conversation_id,
+                &actor_id,
+            )?;
+            Ok(vec![response_msg])
+        }
+
+        ClientMessage::GetHistoryPage {
+            conversation_id,
+            before_message_id,
+            limit,
+        } => {
+            let actor_id = match get_actor_id_for_conversation(interface_state, &conversation_id) {
+                Some(id) => id,
+                None => {
+                    return Ok(vec![create_error_message(
+                        &conversation_id,
+                        "Conversation not found",
+                        "CONVERSATION_NOT_FOUND",
+                    )]);
+                }
+            };
+
+            // Opening a conversation, as opposed to paging back through it
+            if before_message_id.is_none() {
+                set_active_conversation(
+                    interface_state,
+                    connection_id,
+                    conversation_id.clone(),
+                    now(),
+                );
+            }
+
+            // Start right before the cursor, or at the head for the first page
+            let start = match before_message_id {
+                Some(cursor) => chain::get_message(&actor_id, &cursor).map(|m| m.parent_id),
+                None => chain::get_head(&actor_id),
+            };
+            let start = match start {
+                Ok(start) => start,
+                Err(e) => {
+                    return Ok(vec![create_error_message(
+                        &conversation_id,
+                        &e,
+                        "MESSAGE_ERROR",
+                    )]);
+                }
+            };
+
+            let limit = limit.clamp(1, MAX_PAGE_LIMIT);
+            let (mut messages, next_cursor) = match start {
+                Some(start) => match chain::get_page(&actor_id, &start, limit) {
+                    Ok(page) => page,
+                    Err(e) => {
+                        return Ok(vec![create_error_message(
+                            &conversation_id,
+                            &e,
+                            "MESSAGE_ERROR",
+                        )]);
+                    }
+                },
+                None => (Vec::new(), None),
+            };
+
+            state::flag_truncated(interface_state, &conversation_id, &mut messages);
+            Ok(vec![ServerMessage::HistoryPage {
+                conversation_id,
+                messages,
+                next_cursor,
+            }])
+        }
+
+        ClientMessage::GetMessageById {
+            conversation_id,
+            message_id,
+        } => {
+            let actor_id = match get_actor_id_for_conversation(interface_state, &conversation_id) {
+                Some(id) => id,
+                None => {
+                    let error_msg = create_error_message(
+                        &conversation_id,
+                        "Conversation not found",
+                        "CONVERSATION_NOT_FOUND",
+                    );
+                    return Ok(vec![error_msg]);
+                }
+            };
+
+            // Forward request to chat-state actor
+            let chat_state_msg = ChatStateRequest::GetMessage { message_id };
+            let response = forward_to_chat_state(&actor_id, &chat_state_msg)?;
+
+            match response {
+                ChatStateResponse::ChatMessage { mut message } => {
+                    state::flag_truncated(
+                        interface_state,
+                        &conversation_id,
+                        std::slice::from_mut(&mut message),
+                    );
+                    let response_msg = create_message_by_id_response(&conversation_id, message);
+                    return Ok(vec![response_msg]);
+                }
+                ChatStateResponse::Error { error } => {
+                    let error_msg = create_error_message(
+                        &conversation_id,
+                        &format!("Error retrieving message: {:?}", error),
+                        "MESSAGE_ERROR",
+                    );
+                    return Ok(vec![error_msg]);
+                }
+                _ => {
+                    let error_msg = create_error_message(
+                        &conversation_id,
+                        "Unexpected response when retrieving message",
+                        "INTERNAL_ERROR",
+                    );
+                    return Ok(vec![error_msg]);
+                }
+            }
+        }
+
+        ClientMessage::GetHeadId { conversation_id } => {
+            let actor_id = match get_actor_id_for_conversation(interface_state, &conversation_id) {
+                Some(id) => id,
+                None => {
+                    let error_msg = create_error_message(
+                        &conversation_id,
+                        "Conversation not found",
+                        "CONVERSATION_NOT_FOUND",
+                    );
+                    return Ok(vec![error_msg]);
+                }
+            };
+
+            // Forward request to chat-state actor
+            let chat_state_msg = ChatStateRequest::GetHead;
+            let response = forward_to_chat_state(&actor_id, &chat_state_msg)?;
+
+            match response {
+                ChatStateResponse::Head { head } => {
+                    let response_msg = create_head_id_response(&conversation_id, &head);
+                    return Ok(vec![response_msg]);
+                }
+                ChatStateResponse::Error { error } => {
+                    let error_msg = create_error_message(
+                        &conversation_id,
+                        &format!("Error retrieving head: {:?}", error),
+                        "HEAD_ERROR",
+                    );
+                    return Ok(vec![error_msg]);
+                }
+                _ => {
+                    let error_msg = create_error_message(
+                        &conversation_id,
+                        "Unexpected response when retrieving head",
+                        "INTERNAL_ERROR",
+                    );
+                    return Ok(vec![error_msg]);
+                }
+            }
+        }
+        ClientMessage::ListModels => Ok(vec![ServerMessage::ModelList {
+            models: interface_state.models.clone(),
+        }]),
+
+        ClientMessage::ListPresets => {
+            Ok(vec![create_preset_list_message(&interface_state.presets)])
+        }
+
+        ClientMessage::CreatePreset { name, settings } => {
+            let name = name.trim().to_string();
+            if name.is_empty() {
+                return Ok(vec![create_error_message(
+                    "",
+                    "Preset name must not be empty",
+                    "INVALID_PRESET_NAME",
+                )]);
+            }
+            if interface_state.presets.presets.contains_key(&name) {
+                return Ok(vec![create_error_message(
+                    "",
+                    &format!("Preset {} already exists", name),
+                    "PRESET_EXISTS",
+                )]);
+            }
+            if let Some(invalid_msg) = check_settings(interface_state, "", &settings) {
+                return Ok(vec![invalid_msg]);
+            }
+
+            state::save_preset(interface_state, name.clone(), settings.clone());
+            state::store_presets(interface_state)?;
+
+            Ok(vec![ServerMessage::PresetSaved { name, settings }])
+        }
+
+        ClientMessage::UpdatePreset { name, settings } => {
+            // Names are stored trimmed by create_preset
+            let name = name.trim().to_string();
+            if !interface_state.presets.presets.contains_key(&name) {
+                return Ok(vec![create_error_message(
+                    "",
+                    &format!("Preset {} not found", name),
+                    "PRESET_NOT_FOUND",
+                )]);
+            }
+            if let Some(invalid_msg) = check_settings(interface_state, "", &settings) {
+                return Ok(vec![invalid_msg]);
+            }
+
+            state::save_preset(interface_state, name.clone(), settings.clone());
+            state::store_presets(interface_state)?;
+
+            Ok(vec![ServerMessage::PresetSaved { name, settings }])
+        }
+
+        ClientMessage::DeletePreset { name } => {
+            let name = name.trim().to_string();
+            if !state::remove_preset(interface_state, &name) {
+                return Ok(vec![create_error_message(
+                    "",
+                    &format!("Preset {} not found", name),
+                    "PRESET_NOT_FOUND",
+                )]);
+            }
+            state::store_presets(interface_state)?;
+
+            Ok(vec![ServerMessage::PresetDeleted { name }])
+        }
+
+        ClientMessage::SetDefaultPreset { name } => {
+            let name = name.map(|name| name.trim().to_string());
+            if !state::set_default_preset(interface_state, name.clone()) {
+                return Ok(vec![create_error_message(
+                    "",
+                    &format!("Preset {} not found", name.unwrap_or_default()),
+                    "PRESET_NOT_FOUND",
+                )]);
+            }
+            state::store_presets(interface_state)?;
+
+            Ok(vec![create_preset_list_message(&interface_state.presets)])
+        }
+
+        ClientMessage::RenameConversation {
+            conversation_id,
+            new_title,
+        } => {
+            // Check if the conversation exists
+            if !interface_state
+                .conversation_metadata
+                .contains_key(&conversation_id)
+            {
+                let error_msg = create_error_message(
+                    &conversation_id,
+                    "Conversation not found",
+                    "CONVERSATION_NOT_FOUND",
+                );
+                return Ok(vec![error_msg]);
+            }
+
+            // Update the conversation title
+            match state::update_conversation_title(
+                interface_state,
+                &conversation_id,
+                new_title.clone(),
+                TitleSource::User,
+            ) {
+                Ok(_) => {
+                    // Create a response confirming the title was updated
+                    let response_msg =
+                        protocol::create_conversation_renamed_message(&conversation_id, &new_title);
+                    broadcast_to_conversation(
+                        interface_state,
+                        &conversation_id,
+                        &response_msg,
+                        Some(connection_id),
+                    );
+                    broadcast_list_change(
+                        interface_state,
+                        ConversationListChange::Renamed,
+                        &conversation_id,
+                    );
+                    return Ok(vec![response_msg]);
+                }
+                Err(e) => {
+                    let error_msg = create_error_message(
+                        &conversation_id,
+                        &format!("Error updating title: {}", e),
+                        "UPDATE_ERROR",
+                    );
+                    return Ok(vec![error_msg]);
+                }
+            }
+        }
+    }
+}
+
+// Start a `sleep` process whose exit wakes the actor for the next sweep. The actor
+// has no timer callbacks, and timing::sleep would hold up every other event while
+// it waits. Without a timer, the sweep only runs when handlers call it.
+fn start_sweep_timer(interface_state: &mut InterfaceState) {
+    let config = ProcessConfig {
+        program: "sleep".to_string(),
+        args: vec![(IDLE_SWEEP_INTERVAL_MS / 1000).to_string()],
+        cwd: None,
+        env: Vec::new(),
+        buffer_size: 1024,
+        stdout_mode: OutputMode::Raw,
+        stderr_mode: OutputMode::Raw,
+        chunk_size: None,
+    };
+    match os_spawn(&config) {
+        Ok(pid) => interface_state.sweep_timer = Some(pid),
+        Err(e) => {
+            interface_state.sweep_timer = None;
+            log(&format!("Failed to start sweep timer: {}", e));
+        }
+    }
+}
+
+// Close and forget connections that have been idle past the configured timeout,
+// and sessions that have been offline too long to be resumed.
+// The sweep timer and handlers call this, and it only looks for idle connections
+// once per IDLE_SWEEP_INTERVAL_MS.
+fn sweep_idle_connections(interface_state: &mut InterfaceState) {
+    let timestamp = now();
+    if timestamp.saturating_sub(interface_state.last_idle_sweep) < IDLE_SWEEP_INTERVAL_MS {
+        return;
+    }
+    interface_state.last_idle_sweep = timestamp;
+    state::expire_sessions(interface_state, timestamp);
+    streaming::expire_streams(interface_state, timestamp);
+    titling::expire_titling_jobs(interface_state, timestamp);
+
+    for connection_id in state::get_idle_connections(interface_state, timestamp) {
+        log(&format!("Closing idle connection {}", connection_id));
+        drop_connection(interface_state, connection_id, timestamp);
+
+        if let Some(server_id) = interface_state.server_id {
+            if let Err(e) = close_websocket(server_id, connection_id) {
+                log(&format!(
+                    "Failed to close idle connection {}: {}",
+                    connection_id, e
+                ));
+            }
+        }
+    }
+
+    // Subscriptions are reopened by the next streamed completion
+    for channel_id in state::release_orphaned_streams(interface_state) {
+        if let Err(e) = close_channel(&channel_id) {
+            log(&format!("Failed to close channel {}: {}", channel_id, e));
+        }
+    }
+}
+
+// Forget a connection that went away. Its session is kept so the client can resume it,
+// and the completions it was streaming move to another connection viewing them.
+fn drop_connection(interface_state: &mut InterfaceState, connection_id: u64, timestamp: u64) {
+    state::suspend_session(interface_state, connection_id, timestamp);
+    state::rehome_streams(interface_state, connection_id);
+    remove_connection(interface_state, connection_id);
+}
+
+// Turn a configured base path into "" or "/prefix" without a trailing slash
+fn normalize_base_path(base_path: &str) -> String {
+    let trimmed = base_path.trim().trim_matches('/');
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", trimmed)
+    }
+}
+
+// Refuse server settings the actor can't honour rather than silently ignoring them
+fn check_server_config(config: &state::ServerConfig) -> Result<(), String> {
+    if config.max_connections_per_ip.is_some() {
+        return Err(
+            "server.max_connections_per_ip is not supported: the host doesn't \
+            expose client addresses to the actor, so connections can only be capped in \
+            total with max_connections"
+                .to_string(),
+        );
+    }
+    Ok(())
+}
+
+// Make sure the init state says how to spawn chat-state actors and that the manifests
+// it names exist and look like manifests. A broken provider manifest only drops that
+// provider, whose conversations then use the default manifest.
+fn check_chat_state_config(config: Option<ChatStateConfig>) -> Result<ChatStateConfig, String> {
+    let mut config = config.unwrap_or_else(|| ChatStateConfig {
+        manifest_path: DEFAULT_CHAT_STATE_MANIFEST.to_string(),
+        init: None,
+        provider_manifests: HashMap::new(),
+    });
+    if config.manifest_path.trim().is_empty() {
+        return Err("chat_state.manifest_path in the init state is empty".to_string());
+    }
+
+    if let Some(init) = &config.init {
+        if !init.is_object() {
+            return Err("chat_state.init in the init state must be a JSON object".to_string());
+        }
+    }
+
+    check_manifest(&config.manifest_path).map_err(|e| {
+        format!(
+            "chat_state.manifest_path in the init state is unusable: {}",
+            e
+        )
+    })?;
+
+    let mut providers: Vec<(String, String)> = config
+        .provider_manifests
+        .iter()
+        .map(|(provider, path)| (provider.clone(), path.clone()))
+        .collect();
+    providers.sort();
+    for (provider, path) in providers {
+        let problem = if path.trim().is_empty() {
+            Err("the path is empty".to_string())
+        } else {
+            check_manifest(&path)
+        };
+        if let Err(e) = problem {
+            log(&format!(
+                "Ignoring chat_state.provider_manifests.{}, using the default manifest: {}",
+                provider, e
+            ));
+            config.provider_manifests.remove(&provider);
+        }
+    }
+
+    Ok(config)
+}
+
+// Read a manifest file and check that it looks like one
+fn check_manifest(manifest_path: &str) -> Result<(), String> {
+    let bytes = filesystem::read_file(manifest_path)
+        .map_err(|e| format!("can't read {}: {}", manifest_path, e))?;
+    let text =
+        String::from_utf8(bytes).map_err(|e| format!("{} is not UTF-8: {}", manifest_path, e))?;
+    check_manifest_text(&text).map_err(|e| format!("{}: {}", manifest_path, e))
+}
+
+// Check that manifest text sets the top-level keys every Theater manifest needs.
+// There is no TOML parser in the actor, so only `key = value` lines before the first
+// table are looked at.
+fn check_manifest_text(text: &str) -> Result<(), String> {
+    let keys: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .take_while(|line| !line.starts_with('['))
+        .filter_map(|line| line.split_once('='))
+        .filter(|(_, value)| !value.trim().is_empty())
+        .map(|(key, _)| key.trim())
+        .collect();
+
+    for required in ["name", "component_path"] {
+        if !keys.contains(&required) {
+            return Err(format!("no top-level {} is set", required));
+        }
+    }
+    Ok(())
+}
+
+// Remove what a short-lived chat-state actor saved in the shared store. Chat-state
+// actors label their data with the conversation ID they were started for, alone or
+// followed by a dash.
+fn remove_chat_state_labels(store_id: &str, conversation_id: &str) {
+    let labels = match store::list_labels(store_id) {
+        Ok(labels) => labels,
+        Err(e) => {
+            log(&format!(
+                "Failed to list store labels to clean up {}: {}",
+                conversation_id, e
+            ));
+            return;
+        }
+    };
+
+    for label in labels
+        .iter()
+        .filter(|label| is_chat_state_label(label, conversation_id))
+    {
+        if let Err(e) = store::remove_label(store_id, label) {
+            log(&format!("Failed to remove store label {}: {}", label, e));
+        }
+    }
+}
+
+// Whether a store label belongs to the chat-state actor of a conversation
+fn is_chat_state_label(label: &str, conversation_id: &str) -> bool {
+    match label.strip_prefix(conversation_id) {
+        Some(rest) => rest.is_empty() || rest.starts_with('-'),
+        None => false,
+    }
+}
+
+// Start a new chat-state actor for a conversation, using the manifest configured
+// for its provider if there is one
+fn start_chat_state_actor(
+    config: &ChatStateConfig,
+    conversation_id: &str,
+    store_id: &str,
+    provider: Option<&str>,
+) -> Result<String, String> {
+    let manifest_path = provider
+        .and_then(|provider| config.provider_manifests.get(provider))
+        .unwrap_or(&config.manifest_path);
+
+    // Prepare initial state (serialized as JSON), extra configured fields first
+    // so they can't replace the conversation or store
+    let mut initial_state = match &config.init {
+        Some(serde_json::Value::Object(extra)) => extra.clone(),
+        _ => serde_json::Map::new(),
+    };
+    initial_state.insert("conversation_id".to_string(), conversation_id.into());
+    initial_state.insert("store_id".to_string(), store_id.into());
+    let initial_state = serde_json::Value::Object(initial_state);
+
+    // Spawn the actor
+    let actor_id =
+        spawn(manifest_path, Some(initial_state.to_string().as_bytes())).map_err(|e| {
+            format!(
+                "Failed to spawn chat-state actor from manifest {}: {}",
+                manifest_path, e
+            )
+        })?;
+    log(&format!("Spawned chat-state actor with ID: {}", actor_id));
+
+    Ok(actor_id)
+}
+
+// Stop the chat-state actor of a conversation and drop its subscription.
+// A reply still being generated is cancelled first, as if its connection had asked,
+// so that connection gets its final frame and the partial output is kept.
+fn stop_conversation_actor(interface_state: &mut InterfaceState, conversation_id: &str) {
+    if interface_state.active_streams.contains_key(conversation_id) {
+        if let Some(actor_id) = get_actor_id_for_conversation(interface_state, conversation_id) {
+            if let Err(e) =
+                streaming::cancel_streamed_completion(interface_state, conversation_id, &actor_id)
+            {
+                log(&format!(
+                    "Failed to cancel completion of {}: {}",
+                    conversation_id, e
+                ));
+            }
+        }
+    }
+
+    if let Some(channel_id) = get_subscription_for_conversation(interface_state, conversation_id) {
+        if let Err(e) = close_channel(&channel_id) {
+            log(&format!("Failed to close channel {}: {}", channel_id, e));
+        }
+    }
+
+    if let Some(actor_id) = state::detach_conversation_actor(interface_state, conversation_id) {
+        // The actor may already be gone, it is forgotten either way
+        if let Err(e) = stop_child(&actor_id) {
+            log(&format!(
+                "Failed to stop chat-state actor {} for conversation {}: {}",
+                actor_id, conversation_id, e
+            ));
+        }
+    }
+}
+
+// Delete a conversation, stop its chat-state actor and tell the connections viewing it.
+// Returns false if the conversation doesn't exist.
+fn delete_conversation(
+    interface_state: &mut InterfaceState,
+    conversation_id: &str,
+    requester: Option<u64>,
+) -> Result<bool, String> {
+    if !interface_state
+        .conversation_metadata
+        .contains_key(conversation_id)
+    {
+        return Ok(false);
+    }
+
+    let viewers = get_connections_for_conversation(interface_state, conversation_id);
+    let offline_viewers =
+        state::get_offline_sessions(interface_state, Some(conversation_id), requester);
+
+    stop_conversation_actor(interface_state, conversation_id);
+    state::remove_conversation(interface_state, conversation_id);
+
+    store_state(interface_state)?;
+    state::store_branches(interface_state)?;
+    state::store_truncated(interface_state)?;
+    log(&format!("Deleted conversation {}", conversation_id));
+
+    let deleted_msg = create_conversation_deleted_message(conversation_id);
+    for viewer in viewers.into_iter().filter(|v| Some(*v) != requester) {
+        if let Err(e) = send_to_connection(interface_state, viewer, &deleted_msg) {
+            log(&format!("Failed to notify connection {}: {}", viewer, e));
+        }
+    }
+    for session_id in offline_viewers {
+        state::buffer_message(interface_state, &session_id, None, &deleted_msg);
+    }
+    broadcast_list_change(
+        interface_state,
+        ConversationListChange::Deleted,
+        conversation_id,
+    );
+
+    Ok(true)
+}
+
+// Build the branch tree of a conversation from its known heads and current head
+fn get_branch_tree(
+    interface_state: &mut InterfaceState,
+    conversation_id: &str,
+    actor_id: &str,
+) -> Result<(Vec<BranchNode>, Option<String>), String> {
+    let head_id = chain::get_head(actor_id)?;
+
+    let mut heads = state::get_known_heads(interface_state, conversation_id);
+    if let Some(head) = &head_id {
+        if !heads.contains(head) {
+            heads.push(head.clone());
+        }
+    }
+
+    let nodes = chain::get_tree(actor_id, &heads)?;
+
+    // Heads that were built on since are reachable through their children
+    let leaves: Vec<String> = nodes
+        .iter()
+        .filter(|node| node.children.is_empty())
+        .map(|node| node.id.clone())
+        .collect();
+    if state::prune_known_heads(interface_state, conversation_id, &leaves) {
+        state::store_branches(interface_state)?;
+    }
+
+    Ok((nodes, head_id))
+}
+
+// Put a conversation back on the head it had before a failed action, logging
+// rather than returning failures so the original error still reaches the client
+fn restore_head(actor_id: &str, conversation_id: &str, head: Option<String>) {
+    if let Err(e) = chain::set_head(actor_id, head) {
+        log(&format!(
+            "Failed to restore head of conversation {}: {}",
+            conversation_id, e
+        ));
+    }
+}
+
+// Generate a completion on a chat-state actor and build the reply for the client
+// Clients on a new enough protocol only get the messages added after `base_head`.
+// The other connections viewing the conversation get the same update.
+fn generate_completion_reply(
+    interface_state: &mut InterfaceState,
+    connection_id: u64,
+    actor_id: &str,
+    conversation_id: &str,
+    base_head: Option<String>,
+) -> Result<Vec<ServerMessage>, String> {
+    let completion_response =
+        forward_to_chat_state(actor_id, &ChatStateRequest::GenerateCompletion)?;
+
+    match completion_response {
+        // In the new chain-driven architecture, GenerateCompletion returns a Head response
+        // with the ID of the latest message in the chain
+        ChatStateResponse::Head { head } => {
+            if let Some(head) = &head {
+                if state::record_head(interface_state, conversation_id, head) {
+                    state::store_branches(interface_state)?;
+                }
+            }
+
+            // One history request serves the metadata and every form of the update
+            let history = match chain::get_history(actor_id) {
+                Ok(history) => history,
+                Err(e) => {
+                    return Ok(vec![create_error_message(
+                        conversation_id,
+                        &e,
+                        "CHAT_STATE_ERROR",
+                    )]);
+                }
+            };
+            refresh_metadata_from_history(interface_state, conversation_id, &history);
+
+            let (full_msg, delta_msg) =
+                build_messages_update(conversation_id, head, base_head.as_deref(), &history);
+            broadcast_by_version(
+                interface_state,
+                conversation_id,
+                &full_msg,
+                &delta_msg,
+                Some(connection_id),
+            );
+
+            let protocol_version = state::get_protocol_version(interface_state, connection_id);
+            if protocol_version >= DELTA_REPLIES_VERSION {
+                Ok(vec![delta_msg])
+            } else {
+                Ok(vec![full_msg])
+            }
+        }
+        // Handle the old completion response format for backwards compatibility
+        ChatStateResponse::Completion { messages } => {
+            refresh_conversation_metadata(interface_state, conversation_id, actor_id);
+            let response_msg = create_messages_response(conversation_id, messages);
+            broadcast_to_conversation(
+                interface_state,
+                conversation_id,
+                &response_msg,
+                Some(connection_id),
+            );
+            Ok(vec![response_msg])
+        }
+        ChatStateResponse::Error { error } => {
+            // The user message is already in the chain
+            refresh_conversation_metadata(interface_state, conversation_id, actor_id);
+            let error_msg = create_error_message(
+                conversation_id,
+                &format!("Error from chat-state actor: {:?}", error),
+                "CHAT_STATE_ERROR",
+            );
+            Ok(vec![error_msg])
+        }
+        _ => {
+            refresh_conversation_metadata(interface_state, conversation_id, actor_id);
+            let error_msg = create_error_message(
+                conversation_id,
+                "Unexpected response from chat-state actor",
+                "INTERNAL_ERROR",
+            );
+            Ok(vec![error_msg])
+        }
+    }
+}
+
+// Build the update for messages added to a conversation from its current chain,
+// as the whole history and as only the messages after `base_head`
+fn build_messages_update(
+    conversation_id: &str,
+    head: Option<String>,
+    base_head: Option<&str>,
+    history: &[ChatMessage],
+) -> (ServerMessage, ServerMessage) {
+    // Convert ChatMessage objects to Message objects for client compatibility
+    let client_messages: Vec<Message> = history.iter().map(|m| m.message.clone()).collect();
+    let full_msg = create_messages_response(conversation_id, client_messages);
+
+    let delta_msg = match head {
+        Some(head) => ServerMessage::MessagesAppended {
+            conversation_id: conversation_id.to_string(),
+            head_id: head,
+            messages: chain::messages_after(history, base_head),
+        },
+        None => full_msg.clone(),
+    };
+
+    (full_msg, delta_msg)
+}
+
+// Recount the current chain of a conversation and refresh its last message preview,
+// then persist the metadata and announce it to every connection
+fn refresh_conversation_metadata(
+    interface_state: &mut InterfaceState,
+    conversation_id: &str,
+    actor_id: &str,
+) {
+    match chain::get_history(actor_id) {
+        Ok(messages) => refresh_metadata_from_history(interface_state, conversation_id, &messages),
+        Err(e) => log(&format!(
+            "Failed to refresh metadata of {}: {}",
+            conversation_id, e
+        )),
+    }
+}
+
+// Refresh the metadata of a conversation from a history the caller already holds
+fn refresh_metadata_from_history(
+    interface_state: &mut InterfaceState,
+    conversation_id: &str,
+    messages: &[ChatMessage],
+) {
+    if !state::update_conversation_metadata(
+        interface_state,
+        conversation_id,
+        messages.len() as u32,
+        messages.last().map(|m| &m.message),
+        now(),
+    ) {
+        return;
+    }
+
+    if let Err(e) = store_state(interface_state) {
+        log(&format!(
+            "Failed to store metadata of {}: {}",
+            conversation_id, e
+        ));
+    }
+    broadcast_list_change(
+        interface_state,
+        ConversationListChange::MetadataUpdated,
+        conversation_id,
+    );
+
+    // Titled by run_pending_titles once the replies are out
+    if titling::needs_title(interface_state, conversation_id, messages) {
+        interface_state
+            .pending_titles
+            .insert(conversation_id.to_string(), messages.to_vec());
+    }
+}
+
+// Title the conversations queued by refresh_metadata_from_history. Called at the
+// end of a handler, since a title takes a whole completion.
+fn run_pending_titles(interface_state: &mut InterfaceState) {
+    let pending = std::mem::take(&mut interface_state.pending_titles);
+    for (conversation_id, messages) in pending {
+        if let Some(actor_id) = get_actor_id_for_conversation(interface_state, &conversation_id) {
+            title_conversation(interface_state, &conversation_id, &actor_id, &messages);
+        }
+    }
+}
+
+// Give a conversation a generated title and tell every connection about it
+fn title_conversation(
+    interface_state: &mut InterfaceState,
+    conversation_id: &str,
+    actor_id: &str,
+    messages: &[ChatMessage],
+) {
+    let settings = match forward_to_chat_state(actor_id, &ChatStateRequest::GetSettings) {
+        Ok(ChatStateResponse::Settings { settings }) => settings,
+        Ok(response) => {
+            log(&format!(
+                "Unexpected settings response when titling {}: {:?}",
+                conversation_id, response
+            ));
+            return;
+        }
+        Err(e) => {
+            log(&format!(
+                "Failed to get settings for titling {}: {}",
+                conversation_id, e
+            ));
+            return;
+        }
+    };
+
+    if let Err(e) = titling::start_title(interface_state, conversation_id, settings, messages) {
+        log(&format!("Failed to title {}: {}", conversation_id, e));
+    }
+}
+
+// Apply a generated title, unless the user renamed the conversation while it was written
+pub(crate) fn apply_generated_title(
+    interface_state: &mut InterfaceState,
+    conversation_id: &str,
+    title: &str,
+) {
+    let untitled = interface_state
+        .conversation_metadata
+        .get(conversation_id)
+        .map(|meta| meta.title_source == TitleSource::Default)
+        .unwrap_or(false);
+    if !untitled {
+        return;
+    }
+
+    if let Err(e) = state::update_conversation_title(
+        interface_state,
+        conversation_id,
+        title.to_string(),
+        TitleSource::Generated,
+    ) {
+        log(&format!("Failed to title {}: {}", conversation_id, e));
+        return;
+    }
+    log(&format!(
+        "Titled conversation {}: {}",
+        conversation_id, title
+    ));
+
+    let renamed_msg = protocol::create_conversation_renamed_message(conversation_id, title);
+    broadcast_to_conversation(interface_state, conversation_id, &renamed_msg, None);
+    broadcast_list_change(
+        interface_state,
+        ConversationListChange::Renamed,
+        conversation_id,
+    );
+}
+
+// Apply the settings and system prompt requested for a new conversation.
+// A system prompt on its own is applied on top of the actor's default settings.
+fn configure_new_conversation(
+    actor_id: &str,
+    settings: Option<ConversationSettings>,
+    system_prompt: Option<String>,
+) -> Result<(), String> {
+    let settings = match (settings, system_prompt) {
+        (settings, None) => settings,
+        (Some(mut settings), Some(prompt)) => {
+            settings.system_prompt = Some(prompt);
+            Some(settings)
+        }
+        (None, Some(prompt)) => {
+            match forward_to_chat_state(actor_id, &ChatStateRequest::GetSettings)? {
+                ChatStateResponse::Settings { mut settings } => {
+                    settings.system_prompt = Some(prompt);
+                    Some(settings)
+                }
+                ChatStateResponse::Error { error } => return Err(error.message),
+                _ => return Err("Unexpected response when retrieving settings".to_string()),
+            }
+        }
+    };
+
+    seed_chat_state_actor(actor_id, settings, Vec::new())
+}
+
+// Validate settings, returning the `settings_invalid` reply if they are rejected
+fn check_settings(
+    interface_state: &InterfaceState,
+    conversation_id: &str,
+    settings: &ConversationSettings,
+) -> Option<ServerMessage> {
+    let errors = validation::validate_settings(settings, &interface_state.models);
+    if errors.is_empty() {
+        None
+    } else {
+        Some(create_settings_invalid_message(conversation_id, errors))
+    }
+}
+
+// Apply settings and add messages to a freshly started chat-state actor
+fn seed_chat_state_actor(
+    actor_id: &str,
+    settings: Option<ConversationSettings>,
+    messages: Vec<Message>,
+) -> Result<(), String> {
+    if let Some(settings) = settings {
+        match forward_to_chat_state(actor_id, &ChatStateRequest::UpdateSettings { settings })? {
+            ChatStateResponse::Success => {}
+            ChatStateResponse::Error { error } => return Err(error.message),
+            _ => return Err("Unexpected response when updating settings".to_string()),
+        }
+    }
+
+    for message in messages {
+        match forward_to_chat_state(actor_id, &ChatStateRequest::AddMessage { message })? {
+            ChatStateResponse::Success => {}
+            ChatStateResponse::Error { error } => return Err(error.message),
+            _ => return Err("Unexpected response when adding a message".to_string()),
+        }
+    }
+
+    Ok(())
+}
+
+// Forward a message to a chat-state actor
+fn forward_to_chat_state(
+    actor_id: &str,
+    req: &ChatStateRequest,
+) -> Result<ChatStateResponse, String> {
+    // Serialize the message
+    let req_bytes = match serde_json::to_vec(req) {
+        Ok(bytes) => bytes,
+        Err(e) => return Err(format!("Failed to serialize message: {}", e)),
+    };
+
+    // Send request to chat-state actor
+    let response_bytes = match request(actor_id, &req_bytes) {
+        Ok(response) => response,
         Err(e) => return Err(format!("Failed to send request to chat-state actor: {}", e)),
     };
 
@@ -964,8 +3084,8 @@ fn create_head_id_response(conversation_id: &str, head: &Option<String>) -> Serv
     }
 }
 
-// Create a WebSocket text message from a ServerMessage
-fn create_websocket_text_message(message: &ServerMessage) -> Result<WebsocketMessage, String> {
+// Create a WebSocket text message from a ServerMessage or ServerEnvelope
+fn create_websocket_text_message<T: Serialize>(message: &T) -> Result<WebsocketMessage, String> {
     match serde_json::to_string(message) {
         Ok(text) => Ok(WebsocketMessage {
             ty: MessageType::Text,
@@ -976,6 +3096,173 @@ fn create_websocket_text_message(message: &ServerMessage) -> Result<WebsocketMes
     }
 }
 
+// Push a ServerMessage to a connection outside of the request/response cycle
+fn send_to_connection(
+    state: &InterfaceState,
+    connection_id: u64,
+    message: &ServerMessage,
+) -> Result<(), String> {
+    let server_id = match state.server_id {
+        Some(id) => id,
+        None => return Err("HTTP server has not been created".to_string()),
+    };
+
+    let ws_message = create_websocket_text_message(message)?;
+    send_websocket_message(server_id, connection_id, &ws_message)
+}
+
+// Push a ServerMessage wrapped in an envelope, e.g. to echo a request ID
+fn send_envelope_to_connection(
+    state: &InterfaceState,
+    connection_id: u64,
+    envelope: &ServerEnvelope,
+) -> Result<(), String> {
+    let server_id = match state.server_id {
+        Some(id) => id,
+        None => return Err("HTTP server has not been created".to_string()),
+    };
+
+    let ws_message = create_websocket_text_message(envelope)?;
+    send_websocket_message(server_id, connection_id, &ws_message)
+}
+
+// Push a reply to the action `request_id` to a connection, or buffer it if the
+// connection went away and its session can still be resumed
+fn deliver_to_connection(
+    state: &mut InterfaceState,
+    connection_id: u64,
+    request_id: Option<&str>,
+    message: &ServerMessage,
+) -> Result<(), String> {
+    match state::get_offline_session_for_connection(state, connection_id) {
+        Some(session_id) => {
+            state::buffer_message(state, &session_id, request_id, message);
+            Ok(())
+        }
+        None => match request_id {
+            Some(request_id) => send_envelope_to_connection(
+                state,
+                connection_id,
+                &ServerEnvelope::new(Some(request_id.to_string()), message.clone()),
+            ),
+            None => send_to_connection(state, connection_id, message),
+        },
+    }
+}
+
+// Like deliver_to_connection, for a reply whose form depends on the protocol version.
+// An away session keeps both forms, it may come back with another version.
+fn deliver_by_version(
+    state: &mut InterfaceState,
+    connection_id: u64,
+    request_id: Option<&str>,
+    full: &ServerMessage,
+    delta: &ServerMessage,
+) -> Result<(), String> {
+    if let Some(session_id) = state::get_offline_session_for_connection(state, connection_id) {
+        state::buffer_versioned_message(state, &session_id, request_id, full, delta);
+        return Ok(());
+    }
+
+    let message = if state::get_protocol_version(state, connection_id) >= DELTA_REPLIES_VERSION {
+        delta
+    } else {
+        full
+    };
+    deliver_to_connection(state, connection_id, request_id, message)
+}
+
+// Push a ServerMessage to every connection viewing a conversation, except `except`,
+// and buffer it for offline sessions viewing it.
+// Failures are logged so one broken connection doesn't affect the others.
+fn broadcast_to_conversation(
+    state: &mut InterfaceState,
+    conversation_id: &str,
+    message: &ServerMessage,
+    except: Option<u64>,
+) {
+    for viewer in get_connections_for_conversation(state, conversation_id) {
+        if Some(viewer) == except {
+            continue;
+        }
+        if let Err(e) = send_to_connection(state, viewer, message) {
+            log(&format!("Failed to notify connection {}: {}", viewer, e));
+        }
+    }
+
+    for session_id in state::get_offline_sessions(state, Some(conversation_id), except) {
+        state::buffer_message(state, &session_id, None, message);
+    }
+}
+
+// Push an update to the connections viewing a conversation in the form their protocol
+// version expects, `delta` from DELTA_REPLIES_VERSION on and `full` before it.
+// Offline sessions keep both forms, they may come back with another version.
+fn broadcast_by_version(
+    state: &mut InterfaceState,
+    conversation_id: &str,
+    full: &ServerMessage,
+    delta: &ServerMessage,
+    except: Option<u64>,
+) {
+    for viewer in get_connections_for_conversation(state, conversation_id) {
+        if Some(viewer) == except {
+            continue;
+        }
+        let message = if state::get_protocol_version(state, viewer) >= DELTA_REPLIES_VERSION {
+            delta
+        } else {
+            full
+        };
+        if let Err(e) = send_to_connection(state, viewer, message) {
+            log(&format!("Failed to notify connection {}: {}", viewer, e));
+        }
+    }
+
+    for session_id in state::get_offline_sessions(state, Some(conversation_id), except) {
+        state::buffer_versioned_message(state, &session_id, None, full, delta);
+    }
+}
+
+// Tell every connection that an entry of the conversation list changed,
+// and buffer the change for offline sessions
+fn broadcast_list_change(
+    state: &mut InterfaceState,
+    change: ConversationListChange,
+    conversation_id: &str,
+) {
+    let metadata = state.conversation_metadata.get(conversation_id).cloned();
+    let changed_msg = create_conversation_list_changed_message(change, conversation_id, metadata);
+
+    for connection_id in state.connections.keys() {
+        if let Err(e) = send_to_connection(state, *connection_id, &changed_msg) {
+            log(&format!(
+                "Failed to notify connection {}: {}",
+                connection_id, e
+            ));
+        }
+    }
+
+    for session_id in state::get_offline_sessions(state, None, None) {
+        state::buffer_message(state, &session_id, None, &changed_msg);
+    }
+}
+
+// Parse a URL query string into key/value pairs
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .trim_start_matches('?')
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next().unwrap_or("").to_string();
+            let value = parts.next().unwrap_or("").to_string();
+            (key, value)
+        })
+        .collect()
+}
+
 // Generate a unique conversation ID
 fn generate_conversation_id(string: impl AsRef<[u8]>) -> String {
     // Get current timestamp
@@ -992,4 +3279,136 @@ fn generate_conversation_id(string: impl AsRef<[u8]>) -> String {
     format!("conv-{}-{}", timestamp, hash_str)
 }
 
+// Generate a session token for a new connection. It is keyed with the secret drawn
+// at startup, so it can't be worked out from the connection ID and the time.
+fn generate_session_id(session_secret: &str, connection_id: u64) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(session_secret.as_bytes());
+    sha1.update(connection_id.to_string().as_bytes());
+    sha1.update(now().to_string().as_bytes());
+
+    format!("sess-{}", hex::encode(sha1.finalize()))
+}
+
 bindings::export!(Component with_types_in bindings);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_chat_state_label_matches_only_the_actors_labels() {
+        assert!(is_chat_state_label("conv-title", "conv-title"));
+        assert!(is_chat_state_label("conv-title-head", "conv-title"));
+        assert!(!is_chat_state_label("conv", "conv-title"));
+        assert!(!is_chat_state_label("other-conv-title", "conv-title"));
+        assert!(!is_chat_state_label("conv-titled", "conv-title"));
+        assert!(!is_chat_state_label("conv-title2-head", "conv-title"));
+    }
+
+    #[test]
+    fn unparsed_request_id_is_echoed_when_readable() {
+        assert_eq!(
+            unparsed_request_id(r#"{"action": "launch_rockets", "request_id": "req-1"}"#),
+            Some("req-1".to_string())
+        );
+        assert_eq!(unparsed_request_id(r#"{"action": "launch_rockets"}"#), None);
+        assert_eq!(unparsed_request_id(r#"{"request_id": 7}"#), None);
+        assert_eq!(unparsed_request_id("not json"), None);
+    }
+
+    #[test]
+    fn parse_query_splits_pairs() {
+        let params = parse_query("?protocol=2&session=abc&include_archived");
+        assert_eq!(params.get("protocol").map(String::as_str), Some("2"));
+        assert_eq!(params.get("session").map(String::as_str), Some("abc"));
+        assert_eq!(params.get("include_archived").map(String::as_str), Some(""));
+        assert_eq!(params.len(), 3);
+    }
+
+    #[test]
+    fn parse_query_keeps_equals_in_values_and_skips_empty_pairs() {
+        let params = parse_query("token=a=b&&");
+        assert_eq!(params.get("token").map(String::as_str), Some("a=b"));
+        assert_eq!(params.len(), 1);
+        assert!(parse_query("").is_empty());
+    }
+
+    #[test]
+    fn normalize_base_path_adds_leading_and_drops_trailing_slash() {
+        assert_eq!(normalize_base_path("chat"), "/chat");
+        assert_eq!(normalize_base_path("/chat/"), "/chat");
+        assert_eq!(normalize_base_path(" /chat/app/ "), "/chat/app");
+    }
+
+    #[test]
+    fn normalize_base_path_maps_root_to_empty() {
+        assert_eq!(normalize_base_path(""), "");
+        assert_eq!(normalize_base_path("/"), "");
+        assert_eq!(normalize_base_path("//"), "");
+    }
+
+    #[test]
+    fn check_server_config_rejects_per_ip_limit() {
+        let mut config = state::default_server_config();
+        assert!(check_server_config(&config).is_ok());
+
+        config.max_connections_per_ip = Some(10);
+        assert!(check_server_config(&config).is_err());
+
+        let parsed: state::ServerConfig =
+            serde_json::from_str(r#"{"max_connections_per_ip": 10}"#).unwrap();
+        assert!(check_server_config(&parsed).is_err());
+    }
+
+    #[test]
+    fn check_manifest_text_accepts_theater_manifest() {
+        let manifest = "name = \"chat-state\"\ncomponent_path = \"/path/to/component.wasm\"\n\n[[handlers]]\ntype = \"runtime\"\n";
+        assert!(check_manifest_text(manifest).is_ok());
+    }
+
+    #[test]
+    fn check_manifest_text_rejects_missing_keys() {
+        assert!(check_manifest_text("").is_err());
+        assert!(check_manifest_text("name = \"chat-state\"\n").is_err());
+        // Keys inside a table are not top-level
+        let nested = "name = \"chat-state\"\n[config]\ncomponent_path = \"a.wasm\"\n";
+        assert!(check_manifest_text(nested).is_err());
+        assert!(check_manifest_text("name = \"x\"\ncomponent_path =\n").is_err());
+    }
+
+    #[test]
+    fn broadcast_list_change_reaches_away_sessions() {
+        let mut state = state::tests::test_state();
+        register_conversation_actor(
+            &mut state,
+            "conv".to_string(),
+            "actor".to_string(),
+            "Title".to_string(),
+            TitleSource::User,
+            None,
+            0,
+        );
+        state::tests::start_offline_session(&mut state, "first", 1, 0);
+        state::tests::start_offline_session(&mut state, "second", 2, 0);
+
+        broadcast_list_change(&mut state, ConversationListChange::Renamed, "conv");
+
+        for session_id in ["first", "second"] {
+            let buffered = &state.sessions[session_id].buffered;
+            assert_eq!(buffered.len(), 1);
+            match &buffered[0].message {
+                ServerMessage::ConversationListChanged {
+                    change,
+                    conversation_id,
+                    metadata,
+                } => {
+                    assert_eq!(*change, ConversationListChange::Renamed);
+                    assert_eq!(conversation_id, "conv");
+                    assert_eq!(metadata.as_ref().unwrap().title, "Title");
+                }
+                other => panic!("Buffered {:?}", other),
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+
+/// A model clients can choose for a conversation
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ModelInfo {
+    /// Provider serving the model (e.g., "anthropic")
+    pub provider: String,
+
+    /// Model ID passed to the provider
+    pub id: String,
+
+    /// Name shown in the UI
+    pub display_name: String,
+
+    /// Most tokens the model can generate in one completion
+    pub max_output_tokens: u32,
+
+    /// Most tokens the model can take as input
+    pub context_window: u32,
+
+    /// Whether the model can call tools, such as those of MCP servers
+    #[serde(default)]
+    pub supports_tools: bool,
+
+    /// Whether the model accepts images in messages
+    #[serde(default)]
+    pub supports_images: bool,
+
+    /// Lowest temperature the provider accepts for the model
+    #[serde(default)]
+    pub min_temperature: f32,
+
+    /// Highest temperature the provider accepts for the model, 1 unless given
+    #[serde(default = "default_max_temperature")]
+    pub max_temperature: f32,
+}
+
+fn default_max_temperature() -> f32 {
+    1.0
+}
+
+/// Models known without any configuration: provider, ID, display name,
+/// max output tokens and context window. All of them support tools and images
+/// and take a temperature between 0 and 1.
+const BUILTIN_MODELS: &[(&str, &str, &str, u32, u32)] = &[
+    (
+        "anthropic",
+        "claude-3-7-sonnet-20250219",
+        "Claude 3.7 Sonnet",
+        64000,
+        200000,
+    ),
+    (
+        "anthropic",
+        "claude-3-5-sonnet-20241022",
+        "Claude 3.5 Sonnet",
+        8192,
+        200000,
+    ),
+    (
+        "anthropic",
+        "claude-3-5-haiku-20241022",
+        "Claude 3.5 Haiku",
+        8192,
+        200000,
+    ),
+    (
+        "anthropic",
+        "claude-3-opus-20240229",
+        "Claude 3 Opus",
+        4096,
+        200000,
+    ),
+    (
+        "anthropic",
+        "claude-3-haiku-20240307",
+        "Claude 3 Haiku",
+        4096,
+        200000,
+    ),
+];
+
+/// The built-in model catalog
+pub fn builtin_models() -> Vec<ModelInfo> {
+    BUILTIN_MODELS
+        .iter()
+        .map(
+            |(provider, id, display_name, max_output_tokens, context_window)| ModelInfo {
+                provider: provider.to_string(),
+                id: id.to_string(),
+                display_name: display_name.to_string(),
+                max_output_tokens: *max_output_tokens,
+                context_window: *context_window,
+                supports_tools: true,
+                supports_images: true,
+                min_temperature: 0.0,
+                max_temperature: 1.0,
+            },
+        )
+        .collect()
+}
+
+/// Look up a model by provider and ID
+pub fn find_model<'a>(models: &'a [ModelInfo], provider: &str, id: &str) -> Option<&'a ModelInfo> {
+    models
+        .iter()
+        .find(|model| model.provider == provider && model.id == id)
+}
+
+/// Whether any model in the catalog is served by `provider`
+pub fn is_known_provider(models: &[ModelInfo], provider: &str) -> bool {
+    models.iter().any(|model| model.provider == provider)
+}
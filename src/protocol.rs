@@ -1,30 +1,88 @@
+use crate::models::ModelInfo;
 use genai_types::Message;
 use mcp_protocol::tool::Tool;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// Newest protocol version spoken by this server
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest protocol version this server still accepts
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// First protocol version that is sent only the new messages after a completion
+/// (`messages_appended`) instead of the whole history (`messages`)
+pub const DELTA_REPLIES_VERSION: u32 = 2;
+
+/// Optional features advertised to clients in the welcome frame
+pub const CAPABILITIES: &[&str] = &[
+    "request_id",
+    "streaming",
+    "delete",
+    "archive",
+    "fork",
+    "branching",
+    "presets",
+    "models",
+    "heartbeat",
+    "session_resume",
+];
+
+/// Envelope around every client action, carrying an optional correlation ID
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClientEnvelope {
+    /// Client-chosen ID echoed on every reply to this action
+    #[serde(default)]
+    pub request_id: Option<String>,
+
+    #[serde(flatten)]
+    pub message: ClientMessage,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "action")]
 pub enum ClientMessage {
-    /// Create a new conversation
+    /// Request a protocol version for this connection
+    #[serde(rename = "hello")]
+    Hello { protocol_version: u32 },
+
+    /// Create a new conversation, optionally configured and started in one go
     #[serde(rename = "new_conversation")]
-    NewConversation,
+    NewConversation(CreateConversationMessage),
 
     /// Send a message to the server
     #[serde(rename = "send_message")]
     SendMessage {
         conversation_id: String,
         message: Message,
+        /// Stream the completion as `completion_delta` frames instead of
+        /// replying once it has finished
+        #[serde(default)]
+        stream: bool,
     },
 
     /// List all conversations
     #[serde(rename = "list_conversations")]
-    ListConversations,
+    ListConversations {
+        /// Include archived conversations in the list
+        #[serde(default)]
+        include_archived: bool,
+    },
 
     /// Get conversation history
     #[serde(rename = "get_history")]
     GetConversation { conversation_id: String },
 
+    /// Get a page of history, walking back from the head or from `before_message_id`
+    #[serde(rename = "get_history_page")]
+    GetHistoryPage {
+        conversation_id: String,
+        #[serde(default)]
+        before_message_id: Option<String>,
+        #[serde(default = "default_page_limit")]
+        limit: usize,
+    },
+
     /// Update conversation settings
     #[serde(rename = "update_settings")]
     UpdateSettings {
@@ -43,6 +101,68 @@ pub enum ClientMessage {
         new_title: String,
     },
 
+    /// Delete a conversation and stop its chat-state actor
+    #[serde(rename = "delete_conversation")]
+    DeleteConversation { conversation_id: String },
+
+    /// Archive a conversation, stopping its chat-state actor but keeping its history
+    #[serde(rename = "archive_conversation")]
+    ArchiveConversation { conversation_id: String },
+
+    /// Reopen an archived conversation
+    #[serde(rename = "unarchive_conversation")]
+    UnarchiveConversation { conversation_id: String },
+
+    /// Start a new conversation from the chain ending at a message
+    #[serde(rename = "fork_conversation")]
+    ForkConversation {
+        conversation_id: String,
+        message_id: String,
+    },
+
+    /// Replace a past user message with a new version on a sibling branch and regenerate
+    #[serde(rename = "edit_message")]
+    EditMessage {
+        conversation_id: String,
+        message_id: String,
+        message: Message,
+        #[serde(default)]
+        stream: bool,
+    },
+
+    /// Generate an alternative reply next to the current assistant head.
+    /// The model and temperature can be overridden for this run only.
+    #[serde(rename = "regenerate")]
+    Regenerate {
+        conversation_id: String,
+        #[serde(default)]
+        model_config: Option<ModelConfig>,
+        #[serde(default)]
+        temperature: Option<f32>,
+    },
+
+    /// List the direct children of a message across all known branches
+    #[serde(rename = "get_children")]
+    GetChildren {
+        conversation_id: String,
+        message_id: String,
+    },
+
+    /// Get the branch tree of a conversation
+    #[serde(rename = "get_branches")]
+    GetBranches { conversation_id: String },
+
+    /// Move the head of a conversation to another branch
+    #[serde(rename = "switch_branch")]
+    SwitchBranch {
+        conversation_id: String,
+        leaf_id: String,
+    },
+
+    /// Stop the streamed completion running for a conversation
+    #[serde(rename = "cancel_generation")]
+    CancelGeneration { conversation_id: String },
+
     /// Get message by ID for chain-based navigation
     #[serde(rename = "get_message_by_id")]
     GetMessageById {
@@ -53,6 +173,109 @@ pub enum ClientMessage {
     /// Get the current head ID of the conversation chain
     #[serde(rename = "get_head_id")]
     GetHeadId { conversation_id: String },
+
+    /// Heartbeat, answered with `pong`
+    #[serde(rename = "ping")]
+    Ping,
+
+    /// List the models that can be used in settings
+    #[serde(rename = "list_models")]
+    ListModels,
+
+    /// List the settings presets
+    #[serde(rename = "list_presets")]
+    ListPresets,
+
+    /// Save settings as a new named preset
+    #[serde(rename = "create_preset")]
+    CreatePreset {
+        name: String,
+        settings: ConversationSettings,
+    },
+
+    /// Replace the settings of an existing preset
+    #[serde(rename = "update_preset")]
+    UpdatePreset {
+        name: String,
+        settings: ConversationSettings,
+    },
+
+    /// Delete a preset
+    #[serde(rename = "delete_preset")]
+    DeletePreset { name: String },
+
+    /// Choose the preset new conversations start with, or none
+    #[serde(rename = "set_default_preset")]
+    SetDefaultPreset {
+        #[serde(default)]
+        name: Option<String>,
+    },
+}
+
+impl ClientMessage {
+    /// Conversation the action is about, if any
+    pub fn conversation_id(&self) -> Option<&str> {
+        match self {
+            ClientMessage::SendMessage {
+                conversation_id, ..
+            }
+            | ClientMessage::GetConversation { conversation_id }
+            | ClientMessage::GetHistoryPage {
+                conversation_id, ..
+            }
+            | ClientMessage::UpdateSettings {
+                conversation_id, ..
+            }
+            | ClientMessage::GetSettings { conversation_id }
+            | ClientMessage::RenameConversation {
+                conversation_id, ..
+            }
+            | ClientMessage::DeleteConversation { conversation_id }
+            | ClientMessage::ArchiveConversation { conversation_id }
+            | ClientMessage::UnarchiveConversation { conversation_id }
+            | ClientMessage::ForkConversation {
+                conversation_id, ..
+            }
+            | ClientMessage::EditMessage {
+                conversation_id, ..
+            }
+            | ClientMessage::Regenerate {
+                conversation_id, ..
+            }
+            | ClientMessage::GetChildren {
+                conversation_id, ..
+            }
+            | ClientMessage::GetBranches { conversation_id }
+            | ClientMessage::SwitchBranch {
+                conversation_id, ..
+            }
+            | ClientMessage::CancelGeneration { conversation_id }
+            | ClientMessage::GetMessageById {
+                conversation_id, ..
+            }
+            | ClientMessage::GetHeadId { conversation_id } => Some(conversation_id),
+            ClientMessage::Hello { .. }
+            | ClientMessage::NewConversation(_)
+            | ClientMessage::ListConversations { .. }
+            | ClientMessage::Ping
+            | ClientMessage::ListModels
+            | ClientMessage::ListPresets
+            | ClientMessage::CreatePreset { .. }
+            | ClientMessage::UpdatePreset { .. }
+            | ClientMessage::DeletePreset { .. }
+            | ClientMessage::SetDefaultPreset { .. } => None,
+        }
+    }
+}
+
+/// Default number of messages in a history page
+pub const DEFAULT_PAGE_LIMIT: usize = 50;
+
+/// Largest history page a client can ask for
+pub const MAX_PAGE_LIMIT: usize = 500;
+
+fn default_page_limit() -> usize {
+    DEFAULT_PAGE_LIMIT
 }
 
 /// Messages sent from server to clients
@@ -62,6 +285,10 @@ pub struct ChatMessage {
     pub id: Option<String>,
     pub parent_id: Option<String>,
     pub message: Message,
+
+    /// The reply was cancelled and only holds partial output
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -71,7 +298,13 @@ pub enum ServerMessage {
     #[serde(rename = "welcome")]
     Welcome {
         message: String,
-        conversation_id: String,
+        protocol_version: u32,
+        server_version: String,
+        capabilities: Vec<String>,
+        /// Token to pass as `?session=` when reconnecting
+        session_id: String,
+        /// Whether this connection picked up an earlier session
+        resumed: bool,
     },
 
     /// Message indicating a new conversation has been created
@@ -130,6 +363,119 @@ pub enum ServerMessage {
         message: String,
     },
 
+    /// A conversation has been forked into a new one
+    #[serde(rename = "conversation_forked")]
+    ConversationForked {
+        conversation_id: String,
+        metadata: ConversationMetadata,
+    },
+
+    /// Alternative replies after a regenerate, `selected` is the index of the new head
+    #[serde(rename = "regenerated")]
+    Regenerated {
+        conversation_id: String,
+        alternatives: Vec<ChatMessage>,
+        selected: usize,
+    },
+
+    /// Messages appended to the chain by a completion, oldest first
+    #[serde(rename = "messages_appended")]
+    MessagesAppended {
+        conversation_id: String,
+        head_id: String,
+        messages: Vec<ChatMessage>,
+    },
+
+    /// A page of history, oldest first. Pass `next_cursor` as `before_message_id`
+    /// to get the page before it; it is None once the page reaches the root.
+    #[serde(rename = "history_page")]
+    HistoryPage {
+        conversation_id: String,
+        messages: Vec<ChatMessage>,
+        next_cursor: Option<String>,
+    },
+
+    /// Children of a message
+    #[serde(rename = "children")]
+    Children {
+        conversation_id: String,
+        message_id: String,
+        children: Vec<BranchNode>,
+    },
+
+    /// Branch tree of a conversation
+    #[serde(rename = "branches")]
+    Branches {
+        conversation_id: String,
+        nodes: Vec<BranchNode>,
+        leaves: Vec<String>,
+        head_id: Option<String>,
+    },
+
+    /// The head moved to another branch, `messages` is the new chain from the root
+    #[serde(rename = "branch_switched")]
+    BranchSwitched {
+        conversation_id: String,
+        head_id: String,
+        messages: Vec<ChatMessage>,
+    },
+
+    /// Conversation deleted notification
+    #[serde(rename = "conversation_deleted")]
+    ConversationDeleted {
+        conversation_id: String,
+        message: String,
+    },
+
+    /// Conversation archived or unarchived confirmation
+    #[serde(rename = "conversation_archived")]
+    ConversationArchived {
+        conversation_id: String,
+        archived: bool,
+    },
+
+    /// An entry of the conversation list changed, sent to every connection.
+    /// `metadata` is None once the conversation has been deleted.
+    #[serde(rename = "conversation_list_changed")]
+    ConversationListChanged {
+        change: ConversationListChange,
+        conversation_id: String,
+        metadata: Option<ConversationMetadata>,
+    },
+
+    /// Answer to a `ping`, with the server time
+    #[serde(rename = "pong")]
+    Pong { timestamp: u64 },
+
+    /// The model catalog
+    #[serde(rename = "model_list")]
+    ModelList { models: Vec<ModelInfo> },
+
+    /// Settings presets and the default one
+    #[serde(rename = "preset_list")]
+    PresetList {
+        presets: HashMap<String, ConversationSettings>,
+        default_preset: Option<String>,
+    },
+
+    /// A preset was created or updated
+    #[serde(rename = "preset_saved")]
+    PresetSaved {
+        name: String,
+        settings: ConversationSettings,
+    },
+
+    /// A preset was deleted
+    #[serde(rename = "preset_deleted")]
+    PresetDeleted { name: String },
+
+    /// Settings were rejected, with one error per offending field
+    #[serde(rename = "settings_invalid")]
+    SettingsInvalid {
+        conversation_id: String,
+        errors: Vec<FieldError>,
+    },
+
     #[serde(rename = "success")]
     Success,
 
@@ -146,6 +492,73 @@ pub enum ServerMessage {
         conversation_id: String,
         head_id: Option<String>,
     },
+
+    /// A streamed completion has been started
+    #[serde(rename = "completion_started")]
+    CompletionStarted { conversation_id: String },
+
+    /// Partial assistant output for a streamed completion
+    #[serde(rename = "completion_delta")]
+    CompletionDelta {
+        conversation_id: String,
+        delta: String,
+    },
+
+    /// A streamed completion has finished
+    #[serde(rename = "completion_done")]
+    CompletionDone {
+        conversation_id: String,
+        head_id: Option<String>,
+        /// Whole history, for clients before DELTA_REPLIES_VERSION
+        messages: Vec<Message>,
+        /// Messages the completion added to the chain, from DELTA_REPLIES_VERSION on
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        appended: Vec<ChatMessage>,
+        /// The completion was cancelled and only holds partial output
+        truncated: bool,
+    },
+
+    /// A streamed completion was cancelled, `partial` is the output kept so far
+    #[serde(rename = "generation_cancelled")]
+    GenerationCancelled {
+        conversation_id: String,
+        head_id: Option<String>,
+        partial: String,
+    },
+}
+
+/// Envelope around a server message, echoing the request ID of the action it answers
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServerEnvelope {
+    /// Request ID of the client action this replies to, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+
+    #[serde(flatten)]
+    pub message: ServerMessage,
+}
+
+impl ServerEnvelope {
+    pub fn new(request_id: Option<String>, message: ServerMessage) -> Self {
+        ServerEnvelope {
+            request_id,
+            message,
+        }
+    }
+}
+
+/// A message in the branch tree of a conversation
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BranchNode {
+    pub id: String,
+    pub parent_id: Option<String>,
+    pub role: String,
+
+    /// Start of the message text
+    pub preview: String,
+
+    /// IDs of the messages branching off from this one
+    pub children: Vec<String>,
 }
 
 /// Metadata about a conversation for UI display
@@ -168,19 +581,96 @@ pub struct ConversationMetadata {
 
     /// Preview of the last message (truncated)
     pub last_message_preview: Option<String>,
+
+    /// Archived conversations are hidden from listings and have no chat-state actor
+    #[serde(default)]
+    pub archived: bool,
+
+    /// Where this conversation was forked from, if it is a fork
+    #[serde(default)]
+    pub forked_from: Option<ForkOrigin>,
+
+    /// Where the title came from, generated titles never replace one set by the user
+    #[serde(default)]
+    pub title_source: TitleSource,
+
+    /// Provider the conversation was created for, picks the chat-state manifest
+    #[serde(default)]
+    pub provider: Option<String>,
 }
 
-/// Message to create a new chat-state actor
+/// Origin of a conversation title
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TitleSource {
+    /// Placeholder given when the conversation was created
+    #[default]
+    Default,
+    /// Written by a model after the first exchange
+    Generated,
+    /// Chosen by the user
+    User,
+}
+
+/// The conversation and message a fork was taken from
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct CreateConversationMessage {
-    /// Unique ID for the new conversation
+pub struct ForkOrigin {
     pub conversation_id: String,
+    pub message_id: String,
+}
+
+/// What happened to a conversation in a `conversation_list_changed` event
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversationListChange {
+    Created,
+    Renamed,
+    Deleted,
+    Archived,
+    Unarchived,
+    MetadataUpdated,
+}
+
+/// A problem with one field of a request
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FieldError {
+    /// Path of the field, e.g. "mcp_servers[0].config.command"
+    pub field: String,
+    pub message: String,
+}
+
+/// Named settings presets, persisted by the interface actor
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SettingsPresets {
+    pub presets: HashMap<String, ConversationSettings>,
+
+    /// Preset applied to new conversations that don't choose one
+    #[serde(default)]
+    pub default_preset: Option<String>,
+}
+
+/// Options for creating a conversation, all of them optional
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CreateConversationMessage {
+    /// Title to use instead of a generated one
+    pub title: Option<String>,
+
+    /// Settings for the chat-state actor
+    pub settings: Option<ConversationSettings>,
+
+    /// Preset to take the settings from when `settings` is not given.
+    /// Without either the default preset is used, if there is one.
+    pub preset: Option<String>,
 
-    /// Optional system prompt to set the behavior
+    /// System prompt, takes precedence over the one in `settings`
     pub system_prompt: Option<String>,
 
-    /// Model settings
-    pub settings: Option<HashMap<String, serde_json::Value>>,
+    /// First user message, answered like a `send_message`
+    pub message: Option<Message>,
+
+    /// Stream the reply to the first message
+    #[serde(default)]
+    pub stream: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -194,7 +684,7 @@ pub struct ConversationSettings {
     /// Model to use (e.g., "claude-3-7-sonnet-20250219")
     pub model_config: ModelConfig,
 
-    /// Temperature setting (0.0 to 1.0)
+    /// Temperature setting, within the model's range in the catalog
     pub temperature: Option<f32>,
 
     /// Maximum tokens to generate
@@ -215,8 +705,8 @@ pub struct ConversationSettings {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct McpConfig {
-    command: String,
-    args: Vec<String>,
+    pub command: String,
+    pub args: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -248,6 +738,10 @@ pub enum ChatStateRequest {
     GetHead,
     #[serde(rename = "get_message")]
     GetMessage { message_id: String },
+    #[serde(rename = "set_head")]
+    SetHead { head: Option<String> },
+    #[serde(rename = "cancel_completion")]
+    CancelCompletion,
 }
 
 /// Data associated with the response
@@ -279,6 +773,24 @@ pub enum ChatStateResponse {
     ChatMessage { message: ChatMessage },
 }
 
+/// Updates pushed by a chat-state actor over a subscription channel
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum ChatStateUpdate {
+    #[serde(rename = "completion_delta")]
+    CompletionDelta { text: String },
+
+    #[serde(rename = "completion_done")]
+    CompletionDone {
+        head: Option<String>,
+        #[serde(default)]
+        truncated: bool,
+    },
+
+    #[serde(rename = "error")]
+    Error { error: ErrorInfo },
+}
+
 /// Error information
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ErrorInfo {
@@ -294,11 +806,32 @@ pub struct ErrorInfo {
 
 // Helper functions to create common messages
 
+/// Pick the protocol version to use for a connection.
+/// Clients that don't ask for a version get the oldest one, so they keep working.
+pub fn negotiate_protocol_version(requested: Option<u32>) -> Result<u32, String> {
+    match requested {
+        None => Ok(MIN_PROTOCOL_VERSION),
+        Some(version) if version < MIN_PROTOCOL_VERSION => Err(format!(
+            "Protocol version {} is no longer supported, minimum is {}",
+            version, MIN_PROTOCOL_VERSION
+        )),
+        Some(version) => Ok(version.min(PROTOCOL_VERSION)),
+    }
+}
+
 /// Create a welcome message for new connections
-pub fn create_welcome_message() -> ServerMessage {
+pub fn create_welcome_message(
+    protocol_version: u32,
+    session_id: &str,
+    resumed: bool,
+) -> ServerMessage {
     ServerMessage::Welcome {
         message: "Welcome to Claude Chat!".to_string(),
-        conversation_id: "".to_string(),
+        protocol_version,
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        capabilities: CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+        session_id: session_id.to_string(),
+        resumed,
     }
 }
 
@@ -380,6 +913,31 @@ pub fn create_head_id_response(conversation_id: &str, head_id: &Option<String>)
     }
 }
 
+/// Create a completion delta message for streamed completions
+pub fn create_completion_delta_message(conversation_id: &str, delta: &str) -> ServerMessage {
+    ServerMessage::CompletionDelta {
+        conversation_id: conversation_id.to_string(),
+        delta: delta.to_string(),
+    }
+}
+
+/// Create a completion done message for streamed completions
+pub fn create_completion_done_message(
+    conversation_id: &str,
+    head_id: Option<String>,
+    messages: Vec<Message>,
+    appended: Vec<ChatMessage>,
+    truncated: bool,
+) -> ServerMessage {
+    ServerMessage::CompletionDone {
+        conversation_id: conversation_id.to_string(),
+        head_id,
+        messages,
+        appended,
+        truncated,
+    }
+}
+
 /// Create a conversation renamed response
 pub fn create_conversation_renamed_message(conversation_id: &str, title: &str) -> ServerMessage {
     ServerMessage::ConversationRenamed {
@@ -388,3 +946,306 @@ pub fn create_conversation_renamed_message(conversation_id: &str, title: &str) -
         message: format!("Conversation renamed to '{}'", title),
     }
 }
+
+/// Create a conversation deleted message
+pub fn create_conversation_deleted_message(conversation_id: &str) -> ServerMessage {
+    ServerMessage::ConversationDeleted {
+        conversation_id: conversation_id.to_string(),
+        message: "Conversation deleted".to_string(),
+    }
+}
+
+/// Create a conversation archived message
+pub fn create_conversation_archived_message(
+    conversation_id: &str,
+    archived: bool,
+) -> ServerMessage {
+    ServerMessage::ConversationArchived {
+        conversation_id: conversation_id.to_string(),
+        archived,
+    }
+}
+
+/// Create a settings invalid message
+pub fn create_settings_invalid_message(
+    conversation_id: &str,
+    errors: Vec<FieldError>,
+) -> ServerMessage {
+    ServerMessage::SettingsInvalid {
+        conversation_id: conversation_id.to_string(),
+        errors,
+    }
+}
+
+/// Create a preset list message
+pub fn create_preset_list_message(presets: &SettingsPresets) -> ServerMessage {
+    ServerMessage::PresetList {
+        presets: presets.presets.clone(),
+        default_preset: presets.default_preset.clone(),
+    }
+}
+
+/// Create a conversation list changed event
+pub fn create_conversation_list_changed_message(
+    change: ConversationListChange,
+    conversation_id: &str,
+    metadata: Option<ConversationMetadata>,
+) -> ServerMessage {
+    ServerMessage::ConversationListChanged {
+        change,
+        conversation_id: conversation_id.to_string(),
+        metadata,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_envelope_reads_request_id_beside_action() {
+        let envelope: ClientEnvelope = serde_json::from_str(
+            r#"{"action": "get_settings", "conversation_id": "conv", "request_id": "req-1"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(envelope.request_id.as_deref(), Some("req-1"));
+        assert!(matches!(
+            envelope.message,
+            ClientMessage::GetSettings { ref conversation_id } if conversation_id == "conv"
+        ));
+    }
+
+    #[test]
+    fn client_envelope_request_id_is_optional() {
+        let envelope: ClientEnvelope =
+            serde_json::from_str(r#"{"action": "hello", "protocol_version": 2}"#).unwrap();
+
+        assert_eq!(envelope.request_id, None);
+        assert!(matches!(
+            envelope.message,
+            ClientMessage::Hello {
+                protocol_version: 2
+            }
+        ));
+    }
+
+    #[test]
+    fn client_envelope_rejects_unknown_action() {
+        assert!(serde_json::from_str::<ClientEnvelope>(
+            r#"{"action": "launch_rockets", "request_id": "req-1"}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn server_envelope_echoes_request_id() {
+        let envelope = ServerEnvelope::new(
+            Some("req-1".to_string()),
+            create_error_message("conv", "Conversation not found", "CONVERSATION_NOT_FOUND"),
+        );
+        let value = serde_json::to_value(&envelope).unwrap();
+
+        assert_eq!(value["request_id"], "req-1");
+        assert_eq!(value["type"], "error");
+        assert_eq!(value["conversation_id"], "conv");
+        assert_eq!(value["error_code"], "CONVERSATION_NOT_FOUND");
+    }
+
+    #[test]
+    fn server_envelope_omits_missing_request_id() {
+        let envelope = ServerEnvelope::new(None, ServerMessage::Success);
+        let value = serde_json::to_value(&envelope).unwrap();
+
+        assert_eq!(value, serde_json::json!({"type": "success"}));
+    }
+
+    #[test]
+    fn server_envelope_round_trips() {
+        let envelope = ServerEnvelope::new(
+            Some("req-1".to_string()),
+            create_welcome_message(PROTOCOL_VERSION, "session", true),
+        );
+        let json = serde_json::to_string(&envelope).unwrap();
+        let parsed: ServerEnvelope = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.request_id.as_deref(), Some("req-1"));
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+    }
+
+    #[test]
+    fn negotiate_protocol_version_defaults_to_oldest() {
+        assert_eq!(negotiate_protocol_version(None), Ok(MIN_PROTOCOL_VERSION));
+    }
+
+    #[test]
+    fn negotiate_protocol_version_accepts_supported_range() {
+        for version in MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION {
+            assert_eq!(negotiate_protocol_version(Some(version)), Ok(version));
+        }
+    }
+
+    #[test]
+    fn negotiate_protocol_version_rejects_below_minimum() {
+        let error = negotiate_protocol_version(Some(MIN_PROTOCOL_VERSION - 1)).unwrap_err();
+        assert!(error.contains("minimum is"));
+    }
+
+    #[test]
+    fn negotiate_protocol_version_caps_newer_clients() {
+        assert_eq!(
+            negotiate_protocol_version(Some(PROTOCOL_VERSION + 1)),
+            Ok(PROTOCOL_VERSION)
+        );
+        assert_eq!(
+            negotiate_protocol_version(Some(u32::MAX)),
+            Ok(PROTOCOL_VERSION)
+        );
+    }
+
+    #[test]
+    fn chat_state_updates_parse() {
+        let delta: ChatStateUpdate =
+            serde_json::from_str(r#"{"type": "completion_delta", "text": "Hel"}"#).unwrap();
+        assert!(matches!(delta, ChatStateUpdate::CompletionDelta { ref text } if text == "Hel"));
+
+        // Actors that predate cancellation don't send `truncated`
+        let done: ChatStateUpdate =
+            serde_json::from_str(r#"{"type": "completion_done", "head": "msg-2"}"#).unwrap();
+        assert!(matches!(
+            done,
+            ChatStateUpdate::CompletionDone { head: Some(ref head), truncated: false } if head == "msg-2"
+        ));
+
+        let error: ChatStateUpdate = serde_json::from_str(
+            r#"{"type": "error", "error": {"code": "PROVIDER", "message": "overloaded", "details": null}}"#,
+        )
+        .unwrap();
+        assert!(matches!(error, ChatStateUpdate::Error { ref error } if error.code == "PROVIDER"));
+    }
+
+    #[test]
+    fn completion_delta_frame_names_its_conversation() {
+        let value = serde_json::to_value(create_completion_delta_message("conv", "Hel")).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({"type": "completion_delta", "conversation_id": "conv", "delta": "Hel"})
+        );
+    }
+
+    #[test]
+    fn welcome_advertises_version_and_capabilities() {
+        let value = serde_json::to_value(create_welcome_message(1, "session", false)).unwrap();
+
+        assert_eq!(value["type"], "welcome");
+        assert_eq!(value["protocol_version"], 1);
+        assert_eq!(value["server_version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(value["session_id"], "session");
+        assert_eq!(value["resumed"], false);
+        let capabilities: Vec<&str> = value["capabilities"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c.as_str().unwrap())
+            .collect();
+        assert_eq!(capabilities, CAPABILITIES);
+    }
+
+    #[test]
+    fn edit_message_streams_only_when_asked() {
+        let message: ClientMessage = serde_json::from_str(
+            r#"{"action": "edit_message", "conversation_id": "conv", "message_id": "msg",
+                "message": {"role": "user", "content": [{"type": "text", "text": "Hi"}]}}"#,
+        )
+        .unwrap();
+
+        match message {
+            ClientMessage::EditMessage {
+                conversation_id,
+                message_id,
+                message,
+                stream,
+            } => {
+                assert_eq!(conversation_id, "conv");
+                assert_eq!(message_id, "msg");
+                assert_eq!(message.role, "user");
+                assert!(!stream);
+            }
+            other => panic!("Parsed as {:?}", other),
+        }
+    }
+
+    #[test]
+    fn regenerate_overrides_are_optional() {
+        let plain: ClientMessage =
+            serde_json::from_str(r#"{"action": "regenerate", "conversation_id": "conv"}"#).unwrap();
+        assert!(matches!(
+            plain,
+            ClientMessage::Regenerate {
+                model_config: None,
+                temperature: None,
+                ..
+            }
+        ));
+
+        let overridden: ClientMessage = serde_json::from_str(
+            r#"{"action": "regenerate", "conversation_id": "conv", "temperature": 0.5,
+                "model_config": {"model": "claude-3-5-haiku", "provider": "anthropic"}}"#,
+        )
+        .unwrap();
+        match overridden {
+            ClientMessage::Regenerate {
+                model_config: Some(model_config),
+                temperature: Some(temperature),
+                ..
+            } => {
+                assert_eq!(model_config.model, "claude-3-5-haiku");
+                assert_eq!(temperature, 0.5);
+            }
+            other => panic!("Parsed as {:?}", other),
+        }
+    }
+
+    #[test]
+    fn new_conversation_options_are_all_optional() {
+        let bare: ClientMessage =
+            serde_json::from_str(r#"{"action": "new_conversation"}"#).unwrap();
+        match bare {
+            ClientMessage::NewConversation(options) => {
+                assert!(options.title.is_none());
+                assert!(options.settings.is_none());
+                assert!(options.preset.is_none());
+                assert!(options.system_prompt.is_none());
+                assert!(options.message.is_none());
+                assert!(!options.stream);
+            }
+            other => panic!("Parsed as {:?}", other),
+        }
+
+        let full: ClientMessage = serde_json::from_str(
+            r#"{"action": "new_conversation", "title": "Plans", "preset": "fast",
+                "system_prompt": "Be brief", "stream": true,
+                "message": {"role": "user", "content": [{"type": "text", "text": "Hi"}]}}"#,
+        )
+        .unwrap();
+        match full {
+            ClientMessage::NewConversation(options) => {
+                assert_eq!(options.title.as_deref(), Some("Plans"));
+                assert_eq!(options.preset.as_deref(), Some("fast"));
+                assert_eq!(options.system_prompt.as_deref(), Some("Be brief"));
+                assert!(options.message.is_some());
+                assert!(options.stream);
+            }
+            other => panic!("Parsed as {:?}", other),
+        }
+    }
+
+    #[test]
+    fn ping_is_answered_with_timestamped_pong() {
+        let ping: ClientEnvelope = serde_json::from_str(r#"{"action": "ping"}"#).unwrap();
+        assert!(matches!(ping.message, ClientMessage::Ping));
+
+        let pong = serde_json::to_value(ServerMessage::Pong { timestamp: 42 }).unwrap();
+        assert_eq!(pong, serde_json::json!({"type": "pong", "timestamp": 42}));
+    }
+}
@@ -1,8 +1,38 @@
-use crate::protocol::ConversationMetadata;
+use crate::chain::preview_text;
+use crate::models::{builtin_models, ModelInfo};
+use crate::protocol::{
+    ChatMessage, ConversationMetadata, ConversationSettings, ForkOrigin, ServerEnvelope,
+    ServerMessage, SettingsPresets, TitleSource, DELTA_REPLIES_VERSION, MIN_PROTOCOL_VERSION,
+};
+use crate::titling::DEFAULT_TITLE;
 use crate::{log, start_chat_state_actor, store};
+use genai_types::Message;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// Number of characters of the last message shown in the conversation list
+const LIST_PREVIEW_CHARS: usize = 50;
+
+/// How long a session can be resumed after its connection went away, in milliseconds
+const SESSION_TTL_MS: u64 = 30 * 60 * 1000;
+
+/// Most messages held back for a session while its client is away
+const MAX_BUFFERED_MESSAGES: usize = 50;
+
+/// Most bytes of messages held back for a session while its client is away. Buffers
+/// live in the state, which every handler call parses and writes back, so they stay small.
+const MAX_BUFFERED_BYTES: usize = 32 * 1024;
+
+/// Most sessions kept for clients that are away, the longest gone is forgotten first
+const MAX_OFFLINE_SESSIONS: usize = 8;
+
+/// How long a streamed completion may go without output before it is given up on,
+/// in milliseconds
+pub const STREAM_TIMEOUT_MS: u64 = 5 * 60 * 1000;
+
+/// Branch tips remembered per conversation, the oldest is forgotten first
+const MAX_KNOWN_HEADS: usize = 100;
+
 /// Main state structure for the chat-interface actor
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct InterfaceState {
@@ -20,6 +50,55 @@ pub struct InterfaceState {
 
     /// Store ID for the chat interface
     pub store_id: String,
+
+    /// ID of the HTTP server, used to push messages to connections
+    pub server_id: Option<u64>,
+
+    /// Map of subscription channel IDs to the conversation they belong to
+    pub subscriptions: HashMap<String, String>,
+
+    /// Map of conversation IDs to the streamed completion running for them
+    pub active_streams: HashMap<String, StreamInfo>,
+
+    /// Every head each conversation has had, so branches can be found again
+    pub known_heads: HashMap<String, Vec<String>>,
+
+    /// IDs of the replies in each conversation that were cut short by a cancel
+    #[serde(default)]
+    pub truncated_messages: HashMap<String, Vec<String>>,
+
+    /// Named settings presets for new conversations
+    pub presets: SettingsPresets,
+
+    /// Models clients can choose from
+    pub models: Vec<ModelInfo>,
+
+    /// How chat-state actors are spawned
+    pub chat_state_config: ChatStateConfig,
+
+    /// Most connections open at the same time since the actor started
+    pub peak_connections: usize,
+
+    /// When idle connections were last looked for (timestamp)
+    pub last_idle_sweep: u64,
+
+    /// Process ID of the `sleep` whose exit triggers the next sweep
+    #[serde(default)]
+    pub sweep_timer: Option<u64>,
+
+    /// Map of session tokens to the client sessions they resume
+    pub sessions: HashMap<String, SessionInfo>,
+
+    /// Secret that session tokens are derived from, kept under the "session-secret" label
+    pub session_secret: String,
+
+    /// Titles being generated, by the channel their titling actor reports on
+    pub titling_jobs: HashMap<String, TitlingJob>,
+
+    /// Conversations due a generated title once the current replies are out,
+    /// with the chain to title them from. Only lives for one handler call.
+    #[serde(skip)]
+    pub pending_titles: HashMap<String, Vec<ChatMessage>>,
 }
 
 /// Information about a websocket connection
@@ -36,91 +115,345 @@ pub struct ConnectionInfo {
 
     /// When the connection was last active (timestamp)
     pub last_activity: u64,
+
+    /// Protocol version negotiated with the client
+    pub protocol_version: u32,
+
+    /// Session this connection belongs to
+    pub session_id: String,
+
+    /// Whether the connection resumed an earlier session instead of starting one
+    #[serde(default)]
+    pub resumed: bool,
+}
+
+/// A client session, which outlives its WebSocket connections
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SessionInfo {
+    /// Latest connection of the session
+    pub connection_id: u64,
+
+    /// Conversation the client had open when it went away
+    pub active_conversation_id: Option<String>,
+
+    /// Protocol version of the latest connection
+    pub protocol_version: u32,
+
+    /// When the latest connection went away (timestamp), None while connected
+    pub disconnected_at: Option<u64>,
+
+    /// Messages held back while the client is away, oldest first
+    pub buffered: Vec<BufferedMessage>,
+
+    /// Serialized size of the buffered messages, in bytes
+    #[serde(default)]
+    pub buffered_bytes: usize,
+}
+
+/// A message held back for a session while its client is away. Updates whose form
+/// depends on the protocol version keep both forms, and the version the client
+/// resumes with picks one.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BufferedMessage {
+    /// Request ID of the client action this replies to, if any
+    pub request_id: Option<String>,
+
+    /// The message, in the form for clients before DELTA_REPLIES_VERSION if there are two
+    pub message: ServerMessage,
+
+    /// The form for clients from DELTA_REPLIES_VERSION on, if it differs
+    pub delta: Option<ServerMessage>,
+
+    /// Serialized size of both forms, in bytes
+    pub size: usize,
+}
+
+impl BufferedMessage {
+    /// Pick the form of the message for a protocol version
+    pub fn into_envelope(self, protocol_version: u32) -> ServerEnvelope {
+        let message = match self.delta {
+            Some(delta) if protocol_version >= DELTA_REPLIES_VERSION => delta,
+            _ => self.message,
+        };
+        ServerEnvelope::new(self.request_id, message)
+    }
+}
+
+/// A title being written by a temporary chat-state actor
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TitlingJob {
+    /// Conversation the title is for
+    pub conversation_id: String,
+
+    /// Temporary chat-state actor writing the title
+    pub actor_id: String,
+
+    /// When the title is given up on (timestamp)
+    pub deadline: u64,
+}
+
+/// A streamed completion in progress
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StreamInfo {
+    /// Connection that started the completion
+    pub connection_id: u64,
+
+    /// Request ID of the action that started the completion, echoed on its frames
+    #[serde(default)]
+    pub request_id: Option<String>,
+
+    /// Assistant output received so far
+    pub partial: String,
+
+    /// Head of the chain before the completion's user message was added
+    #[serde(default)]
+    pub base_head: Option<String>,
+
+    /// Head to go back to if the completion fails, set by edits
+    #[serde(default)]
+    pub restore_head: Option<Option<String>>,
+
+    /// When the completion is given up on unless more output arrives (timestamp)
+    #[serde(default)]
+    pub deadline: u64,
 }
 
 /// Configuration for the HTTP server
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ServerConfig {
     /// Port to listen on
+    #[serde(default = "default_port")]
     pub port: u16,
 
     /// Host address to bind to
+    #[serde(default = "default_host")]
     pub host: String,
 
     /// Maximum number of connections to allow
+    #[serde(default = "default_max_connections")]
     pub max_connections: u32,
+
+    /// Certificate and key to serve HTTPS and WSS with
+    #[serde(default)]
+    pub tls: Option<TlsSettings>,
+
+    /// Prefix of every route, e.g. "/chat" behind a reverse proxy. Empty for the root.
+    #[serde(default)]
+    pub base_path: String,
+
+    /// Milliseconds without any frame after which a connection is closed, 0 to never
+    #[serde(default = "default_idle_timeout_ms")]
+    pub idle_timeout_ms: u64,
+
+    /// Per-client-IP connection cap. The host doesn't tell the actor who is connecting,
+    /// so this can't be enforced and setting it is refused at startup.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_connections_per_ip: Option<u32>,
+}
+
+/// Paths of the TLS certificate and private key
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TlsSettings {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+fn default_port() -> u16 {
+    8080
 }
 
-/// Initialize a new state with default values
-pub fn initialize_state(store_id: &str) -> InterfaceState {
+fn default_host() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_max_connections() -> u32 {
+    1000
+}
+
+fn default_idle_timeout_ms() -> u64 {
+    5 * 60 * 1000
+}
+
+/// Server configuration used when the init state has none
+pub fn default_server_config() -> ServerConfig {
+    ServerConfig {
+        port: default_port(),
+        host: default_host(),
+        max_connections: default_max_connections(),
+        tls: None,
+        base_path: String::new(),
+        idle_timeout_ms: default_idle_timeout_ms(),
+        max_connections_per_ip: None,
+    }
+}
+
+/// How chat-state actors are spawned, read from the init state
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatStateConfig {
+    /// Manifest of the chat-state actor
+    pub manifest_path: String,
+
+    /// Extra fields merged into the initial state of every chat-state actor
+    #[serde(default)]
+    pub init: Option<serde_json::Value>,
+
+    /// Manifests to use instead of `manifest_path` for conversations on a provider
+    #[serde(default)]
+    pub provider_manifests: HashMap<String, String>,
+}
+
+/// Initialize a new state with default values.
+/// The model catalog is read from the store, then `configured_models`, then the built-in list.
+pub fn initialize_state(
+    store_id: &str,
+    server_config: ServerConfig,
+    chat_state_config: ChatStateConfig,
+    configured_models: Option<Vec<ModelInfo>>,
+) -> Result<InterfaceState, String> {
     // Check if there are any conversations in the given store
     // If not, create a new state with default values
-    let conversations =
-        store::get_by_label(store_id, "conversations").expect("Failed to get conversations");
-
-    let mut actors = HashMap::new();
-
-    let metadata = match conversations {
-        Some(content_ref) => {
-            let data = store::get(store_id, &content_ref).expect("Failed to get conversation data");
-            // Deserialize the data into conversation metadata
-            let metadata: HashMap<String, ConversationMetadata> =
-                serde_json::from_slice(&data).unwrap_or_default();
-            log(&format!("Loaded conversations from store: {:?}", metadata));
-
-            // For each conversation, start a new chat-state actor
-            for conversation_id in metadata.keys() {
-                log(&format!(
-                    "Starting chat state actor for conversation: {}",
-                    conversation_id
-                ));
-                let chat_state_actor_id = start_chat_state_actor(conversation_id, store_id)
-                    .expect("Failed to start chat state actor");
-                log(&format!(
-                    "Started chat state actor with ID: {}",
-                    chat_state_actor_id
-                ));
-
-                log(&format!(
-                    "Registering conversation actor ID: {} for conversation: {}",
-                    chat_state_actor_id, conversation_id
-                ));
-
-                actors.insert(conversation_id.clone(), chat_state_actor_id.clone());
-
-                log(&format!(
-                    "Inserted actor ID for conversation {}: {}",
-                    conversation_id, chat_state_actor_id
-                ));
+    let mut metadata: HashMap<String, ConversationMetadata> =
+        match load_label(store_id, "conversations")? {
+            Some(data) => {
+                // Deserialize the data into conversation metadata
+                let metadata = serde_json::from_slice(&data).unwrap_or_default();
+                log(&format!("Loaded conversations from store: {:?}", metadata));
+                metadata
             }
+            None => {
+                log("No conversations found in store, initializing empty state");
+                HashMap::new()
+            }
+        };
 
-            metadata
-        }
-        None => {
-            log("No conversations found in store, initializing empty state");
-            HashMap::new()
-        }
+    let migrated = migrate_title_sources(&mut metadata);
+
+    let known_heads = match load_label(store_id, "branches")? {
+        Some(data) => serde_json::from_slice(&data).unwrap_or_default(),
+        None => HashMap::new(),
     };
 
-    log("Initialized interface state");
+    let truncated_messages = match load_label(store_id, "truncated")? {
+        Some(data) => serde_json::from_slice(&data).unwrap_or_default(),
+        None => HashMap::new(),
+    };
+
+    let presets = match load_label(store_id, "presets")? {
+        Some(data) => serde_json::from_slice(&data).unwrap_or_default(),
+        None => SettingsPresets::default(),
+    };
+
+    let stored_models = match load_label(store_id, "models")? {
+        Some(data) => match serde_json::from_slice::<Vec<ModelInfo>>(&data) {
+            Ok(models) => Some(models),
+            Err(e) => {
+                log(&format!("Ignoring invalid model catalog in store: {}", e));
+                None
+            }
+        },
+        None => None,
+    };
+    let models = stored_models
+        .or(configured_models)
+        .unwrap_or_else(builtin_models);
+
+    let session_secret = load_session_secret(store_id)?;
 
-    let interface_state = InterfaceState {
+    let mut interface_state = InterfaceState {
         connections: HashMap::new(),
-        conversation_actors: actors,
+        conversation_actors: HashMap::new(),
         conversation_metadata: metadata,
         store_id: store_id.to_string(),
-        server_config: ServerConfig {
-            port: 8080,
-            host: "0.0.0.0".to_string(),
-            max_connections: 1000,
-        },
+        server_id: None,
+        subscriptions: HashMap::new(),
+        active_streams: HashMap::new(),
+        known_heads,
+        truncated_messages,
+        presets,
+        models,
+        chat_state_config,
+        server_config,
+        peak_connections: 0,
+        last_idle_sweep: 0,
+        sweep_timer: None,
+        sessions: HashMap::new(),
+        session_secret,
+        titling_jobs: HashMap::new(),
+        pending_titles: HashMap::new(),
     };
 
-    interface_state
+    if migrated {
+        store_state(&interface_state)?;
+    }
+
+    // For each open conversation, start a new chat-state actor.
+    // Archived ones get an actor when they are unarchived.
+    let open: Vec<(String, Option<String>)> = interface_state
+        .conversation_metadata
+        .values()
+        .filter(|meta| !meta.archived)
+        .map(|meta| (meta.id.clone(), meta.provider.clone()))
+        .collect();
+    for (conversation_id, provider) in open {
+        log(&format!(
+            "Starting chat state actor for conversation: {}",
+            conversation_id
+        ));
+        let chat_state_actor_id = start_chat_state_actor(
+            &interface_state.chat_state_config,
+            &conversation_id,
+            store_id,
+            provider.as_deref(),
+        )
+        .map_err(|e| format!("Failed to restore conversation {}: {}", conversation_id, e))?;
+        log(&format!(
+            "Started chat state actor with ID: {}",
+            chat_state_actor_id
+        ));
+
+        interface_state
+            .conversation_actors
+            .insert(conversation_id, chat_state_actor_id);
+    }
+
+    log("Initialized interface state");
+
+    Ok(interface_state)
+}
+
+/// Read the data stored under a label, if any
+fn load_label(store_id: &str, label: &str) -> Result<Option<Vec<u8>>, String> {
+    let content_ref = store::get_by_label(store_id, label)
+        .map_err(|e| format!("Failed to get {} from store: {}", label, e))?;
+    match content_ref {
+        Some(content_ref) => store::get(store_id, &content_ref)
+            .map(Some)
+            .map_err(|e| format!("Failed to read {} from store: {}", label, e)),
+        None => Ok(None),
+    }
+}
+
+/// Load the secret session tokens are keyed with, drawing it on first start. Actors get
+/// no random numbers from the runtime, but a new store ID is a random UUID drawn by the
+/// host. The store it names is left unused.
+fn load_session_secret(store_id: &str) -> Result<String, String> {
+    if let Some(data) = load_label(store_id, "session-secret")? {
+        return String::from_utf8(data).map_err(|e| format!("Invalid session secret: {}", e));
+    }
+
+    let secret = store::new().map_err(|e| format!("Failed to draw a session secret: {}", e))?;
+    store::store_at_label(store_id, "session-secret", secret.as_bytes())
+        .map_err(|e| format!("Failed to store session secret: {}", e))?;
+    Ok(secret)
 }
 
 /// Add a new connection to the state
-pub fn add_connection(state: &mut InterfaceState, connection_id: u64, timestamp: u64) {
+pub fn add_connection(
+    state: &mut InterfaceState,
+    connection_id: u64,
+    protocol_version: u32,
+    timestamp: u64,
+) {
     state.connections.insert(
         connection_id,
         ConnectionInfo {
@@ -128,8 +461,316 @@ pub fn add_connection(state: &mut InterfaceState, connection_id: u64, timestamp:
             active_conversation_id: None,
             connected_at: timestamp,
             last_activity: timestamp,
+            protocol_version,
+            session_id: String::new(),
+            resumed: false,
         },
     );
+    state.peak_connections = state.peak_connections.max(state.connections.len());
+}
+
+/// Record activity on a connection
+pub fn touch_connection(state: &mut InterfaceState, connection_id: u64, timestamp: u64) {
+    if let Some(conn) = state.connections.get_mut(&connection_id) {
+        conn.last_activity = timestamp;
+    }
+}
+
+/// Get the connections that have been quiet for longer than the idle timeout
+pub fn get_idle_connections(state: &InterfaceState, timestamp: u64) -> Vec<u64> {
+    let timeout = state.server_config.idle_timeout_ms;
+    if timeout == 0 {
+        return Vec::new();
+    }
+
+    state
+        .connections
+        .values()
+        .filter(|conn| timestamp.saturating_sub(conn.last_activity) > timeout)
+        .map(|conn| conn.connection_id)
+        .collect()
+}
+
+/// Start a new session for a connection
+pub fn start_session(state: &mut InterfaceState, session_id: String, connection_id: u64) {
+    let protocol_version = get_protocol_version(state, connection_id);
+    if let Some(conn) = state.connections.get_mut(&connection_id) {
+        conn.session_id = session_id.clone();
+    }
+
+    state.sessions.insert(
+        session_id,
+        SessionInfo {
+            connection_id,
+            active_conversation_id: None,
+            protocol_version,
+            disconnected_at: None,
+            buffered: Vec::new(),
+            buffered_bytes: 0,
+        },
+    );
+}
+
+/// Move a session to a new connection, restoring the conversation it had open and
+/// handing it any streamed completion still running. Returns the messages buffered
+/// while the client was away, in the form for the new connection's protocol version,
+/// or None if there is no such session or its connection is still open.
+pub fn resume_session(
+    state: &mut InterfaceState,
+    session_id: &str,
+    connection_id: u64,
+) -> Option<Vec<ServerEnvelope>> {
+    let protocol_version = get_protocol_version(state, connection_id);
+    let session = state.sessions.get_mut(session_id)?;
+    let previous_connection = session.connection_id;
+    // A token is no way to take over a connection that is still open
+    if session.disconnected_at.is_none() && state.connections.contains_key(&previous_connection) {
+        return None;
+    }
+    session.connection_id = connection_id;
+    session.protocol_version = protocol_version;
+    session.disconnected_at = None;
+    session.buffered_bytes = 0;
+    let buffered: Vec<ServerEnvelope> = std::mem::take(&mut session.buffered)
+        .into_iter()
+        .map(|message| message.into_envelope(protocol_version))
+        .collect();
+
+    // The conversation may have been deleted or archived in the meantime
+    let active_conversation_id = session
+        .active_conversation_id
+        .clone()
+        .filter(|id| state.conversation_actors.contains_key(id));
+    if let Some(conn) = state.connections.get_mut(&connection_id) {
+        conn.session_id = session_id.to_string();
+        conn.active_conversation_id = active_conversation_id;
+        conn.resumed = true;
+    }
+
+    for stream in state.active_streams.values_mut() {
+        if stream.connection_id == previous_connection {
+            stream.connection_id = connection_id;
+        }
+    }
+
+    Some(buffered)
+}
+
+/// Keep the session of a connection that is going away so it can be resumed
+pub fn suspend_session(state: &mut InterfaceState, connection_id: u64, timestamp: u64) {
+    let conn = match state.connections.get(&connection_id) {
+        Some(conn) => conn,
+        None => return,
+    };
+
+    if let Some(session) = state.sessions.get_mut(&conn.session_id) {
+        // A newer connection may have taken the session over already
+        if session.connection_id == connection_id {
+            session.active_conversation_id = conn.active_conversation_id.clone();
+            session.protocol_version = conn.protocol_version;
+            session.disconnected_at = Some(timestamp);
+        }
+    }
+
+    // Forget the clients gone longest once too many are away
+    let mut offline: Vec<(u64, String)> = state
+        .sessions
+        .iter()
+        .filter_map(|(id, session)| session.disconnected_at.map(|at| (at, id.clone())))
+        .collect();
+    if offline.len() > MAX_OFFLINE_SESSIONS {
+        offline.sort();
+        for (_, session_id) in offline.iter().take(offline.len() - MAX_OFFLINE_SESSIONS) {
+            state.sessions.remove(session_id);
+        }
+    }
+}
+
+/// Get the sessions whose client is away.
+/// With a conversation ID only the sessions that had it open are returned,
+/// sessions last seen on connection `except` are left out.
+pub fn get_offline_sessions(
+    state: &InterfaceState,
+    conversation_id: Option<&str>,
+    except: Option<u64>,
+) -> Vec<String> {
+    state
+        .sessions
+        .iter()
+        .filter(|(_, session)| session.disconnected_at.is_some())
+        .filter(|(_, session)| Some(session.connection_id) != except)
+        .filter(|(_, session)| {
+            conversation_id.is_none()
+                || session.active_conversation_id.as_deref() == conversation_id
+        })
+        .map(|(id, _)| id.clone())
+        .collect()
+}
+
+/// Get the session token of a connection
+pub fn get_session_id(state: &InterfaceState, connection_id: u64) -> String {
+    state
+        .connections
+        .get(&connection_id)
+        .map(|conn| conn.session_id.clone())
+        .unwrap_or_default()
+}
+
+/// Whether a connection resumed an earlier session
+pub fn is_resumed_connection(state: &InterfaceState, connection_id: u64) -> bool {
+    state
+        .connections
+        .get(&connection_id)
+        .map(|conn| conn.resumed)
+        .unwrap_or(false)
+}
+
+/// Get the away session whose latest connection was `connection_id`
+pub fn get_offline_session_for_connection(
+    state: &InterfaceState,
+    connection_id: u64,
+) -> Option<String> {
+    state
+        .sessions
+        .iter()
+        .find(|(_, session)| {
+            session.connection_id == connection_id && session.disconnected_at.is_some()
+        })
+        .map(|(id, _)| id.clone())
+}
+
+/// Hold a message back for an away session, dropping the oldest once the buffer is full.
+/// Streamed deltas are not kept, a resumed client gets the whole reply from `completion_done`.
+pub fn buffer_message(
+    state: &mut InterfaceState,
+    session_id: &str,
+    request_id: Option<&str>,
+    message: &ServerMessage,
+) {
+    if matches!(message, ServerMessage::CompletionDelta { .. }) {
+        return;
+    }
+    push_buffered(state, session_id, request_id, message, None);
+}
+
+/// Hold back an update whose form depends on the protocol version, `full` for clients
+/// before DELTA_REPLIES_VERSION and `delta` from it on
+pub fn buffer_versioned_message(
+    state: &mut InterfaceState,
+    session_id: &str,
+    request_id: Option<&str>,
+    full: &ServerMessage,
+    delta: &ServerMessage,
+) {
+    push_buffered(state, session_id, request_id, full, Some(delta));
+}
+
+fn push_buffered(
+    state: &mut InterfaceState,
+    session_id: &str,
+    request_id: Option<&str>,
+    message: &ServerMessage,
+    delta: Option<&ServerMessage>,
+) {
+    let session = match state.sessions.get_mut(session_id) {
+        Some(session) => session,
+        None => return,
+    };
+
+    let serialized_size =
+        |message: &ServerMessage| serde_json::to_vec(message).map_or(0, |bytes| bytes.len());
+    let size = serialized_size(message) + delta.map_or(0, serialized_size);
+    if size > MAX_BUFFERED_BYTES {
+        log(&format!(
+            "Not buffering a {} byte message for session {}, it is over the limit",
+            size, session_id
+        ));
+        return;
+    }
+
+    while !session.buffered.is_empty()
+        && (session.buffered.len() >= MAX_BUFFERED_MESSAGES
+            || session.buffered_bytes + size > MAX_BUFFERED_BYTES)
+    {
+        let dropped = session.buffered.remove(0);
+        session.buffered_bytes = session.buffered_bytes.saturating_sub(dropped.size);
+    }
+
+    session.buffered.push(BufferedMessage {
+        request_id: request_id.map(String::from),
+        message: message.clone(),
+        delta: delta.cloned(),
+        size,
+    });
+    session.buffered_bytes += size;
+}
+
+/// Forget sessions whose client has been away for longer than they can be resumed
+pub fn expire_sessions(state: &mut InterfaceState, timestamp: u64) {
+    state
+        .sessions
+        .retain(|_, session| match session.disconnected_at {
+            Some(disconnected_at) => timestamp.saturating_sub(disconnected_at) <= SESSION_TTL_MS,
+            None => true,
+        });
+}
+
+/// Hand the streamed completions started by a closing connection to another connection
+/// viewing the same conversation. Streams nobody else views keep their owner, so a
+/// resumed session can pick them up and the completion still finishes normally.
+pub fn rehome_streams(state: &mut InterfaceState, connection_id: u64) {
+    for (conversation_id, stream) in state.active_streams.iter_mut() {
+        if stream.connection_id != connection_id {
+            continue;
+        }
+        if let Some(viewer) = state.connections.values().find(|conn| {
+            conn.connection_id != connection_id
+                && conn.active_conversation_id.as_deref() == Some(conversation_id.as_str())
+        }) {
+            stream.connection_id = viewer.connection_id;
+        }
+    }
+}
+
+/// Forget the streamed completions nobody can receive any more, because their
+/// connection is gone and no session can take them over, and the subscriptions no
+/// stream needs. Returns the subscription channels to close.
+pub fn release_orphaned_streams(state: &mut InterfaceState) -> Vec<String> {
+    let connections = &state.connections;
+    let sessions = &state.sessions;
+    state.active_streams.retain(|_, stream| {
+        connections.contains_key(&stream.connection_id)
+            || sessions
+                .values()
+                .any(|session| session.connection_id == stream.connection_id)
+    });
+
+    let active_streams = &state.active_streams;
+    let released: Vec<String> = state
+        .subscriptions
+        .iter()
+        .filter(|(_, conversation_id)| !active_streams.contains_key(conversation_id.as_str()))
+        .map(|(channel_id, _)| channel_id.clone())
+        .collect();
+    for channel_id in &released {
+        state.subscriptions.remove(channel_id);
+    }
+    released
+}
+
+/// Get the conversations whose streamed completion is past its deadline
+pub fn get_expired_streams(state: &InterfaceState, timestamp: u64) -> Vec<String> {
+    state
+        .active_streams
+        .iter()
+        .filter(|(_, stream)| timestamp > stream.deadline)
+        .map(|(conversation_id, _)| conversation_id.clone())
+        .collect()
+}
+
+/// Whether another connection would go over the configured limit
+pub fn at_connection_limit(state: &InterfaceState) -> bool {
+    state.connections.len() >= state.server_config.max_connections as usize
 }
 
 /// Remove a connection from the state
@@ -137,6 +778,31 @@ pub fn remove_connection(state: &mut InterfaceState, connection_id: u64) -> bool
     state.connections.remove(&connection_id).is_some()
 }
 
+/// Set the negotiated protocol version of a connection
+pub fn set_protocol_version(
+    state: &mut InterfaceState,
+    connection_id: u64,
+    protocol_version: u32,
+    timestamp: u64,
+) -> bool {
+    if let Some(conn) = state.connections.get_mut(&connection_id) {
+        conn.protocol_version = protocol_version;
+        conn.last_activity = timestamp;
+        true
+    } else {
+        false
+    }
+}
+
+/// Get the negotiated protocol version of a connection
+pub fn get_protocol_version(state: &InterfaceState, connection_id: u64) -> u32 {
+    state
+        .connections
+        .get(&connection_id)
+        .map(|conn| conn.protocol_version)
+        .unwrap_or(MIN_PROTOCOL_VERSION)
+}
+
 /// Associate a connection with a conversation
 pub fn set_active_conversation(
     state: &mut InterfaceState,
@@ -165,12 +831,58 @@ pub fn store_state(state: &InterfaceState) -> Result<(), String> {
     Ok(())
 }
 
+/// Persist the known heads of every conversation
+pub fn store_branches(state: &InterfaceState) -> Result<(), String> {
+    let branches = serde_json::to_vec(&state.known_heads)
+        .map_err(|e| format!("Failed to serialize branches: {}", e))?;
+    store::store_at_label(&state.store_id, "branches", &branches)
+        .map_err(|e| format!("Failed to store branches: {}", e))?;
+    Ok(())
+}
+
+/// Persist which replies were cut short
+pub fn store_truncated(state: &InterfaceState) -> Result<(), String> {
+    let truncated = serde_json::to_vec(&state.truncated_messages)
+        .map_err(|e| format!("Failed to serialize truncated messages: {}", e))?;
+    store::store_at_label(&state.store_id, "truncated", &truncated)
+        .map_err(|e| format!("Failed to store truncated messages: {}", e))?;
+    Ok(())
+}
+
+/// Persist the settings presets
+pub fn store_presets(state: &InterfaceState) -> Result<(), String> {
+    let presets = serde_json::to_vec(&state.presets)
+        .map_err(|e| format!("Failed to serialize presets: {}", e))?;
+    store::store_at_label(&state.store_id, "presets", &presets)
+        .map_err(|e| format!("Failed to store presets: {}", e))?;
+    Ok(())
+}
+
+/// Titles stored before title sources were tracked all read as `Default`. Only the
+/// placeholders really are; anything else was chosen by the user, e.g. a rename or a
+/// fork's "Fork of ..." title, and must not be replaced by a generated one.
+/// Returns true if any conversation changed.
+fn migrate_title_sources(metadata: &mut HashMap<String, ConversationMetadata>) -> bool {
+    let mut migrated = false;
+    for meta in metadata.values_mut() {
+        let placeholder =
+            meta.title == DEFAULT_TITLE || meta.title.starts_with("Conversation conv-");
+        if meta.title_source == TitleSource::Default && !placeholder {
+            meta.title_source = TitleSource::User;
+            migrated = true;
+        }
+    }
+    migrated
+}
+
 /// Register a new chat-state actor in the registry
 pub fn register_conversation_actor(
     state: &mut InterfaceState,
     conversation_id: String,
     actor_id: String,
     title: String,
+    title_source: TitleSource,
+    provider: Option<String>,
     timestamp: u64,
 ) {
     // Save the actor ID
@@ -188,54 +900,255 @@ pub fn register_conversation_actor(
             updated_at: timestamp,
             message_count: 0,
             last_message_preview: None,
+            archived: false,
+            forked_from: None,
+            title_source,
+            provider,
         },
     );
 }
 
+/// Forget the chat-state actor of a conversation, returning its ID.
+/// Connections viewing the conversation are detached from it.
+pub fn detach_conversation_actor(
+    state: &mut InterfaceState,
+    conversation_id: &str,
+) -> Option<String> {
+    state.active_streams.remove(conversation_id);
+    state
+        .subscriptions
+        .retain(|_, conv_id| conv_id.as_str() != conversation_id);
+
+    for conn in state.connections.values_mut() {
+        if conn.active_conversation_id.as_deref() == Some(conversation_id) {
+            conn.active_conversation_id = None;
+        }
+    }
+    for session in state.sessions.values_mut() {
+        if session.active_conversation_id.as_deref() == Some(conversation_id) {
+            session.active_conversation_id = None;
+        }
+    }
+
+    state.conversation_actors.remove(conversation_id)
+}
+
+/// Remove a conversation from the registry, returning its chat-state actor ID
+pub fn remove_conversation(state: &mut InterfaceState, conversation_id: &str) -> Option<String> {
+    state.conversation_metadata.remove(conversation_id);
+    state.known_heads.remove(conversation_id);
+    state.truncated_messages.remove(conversation_id);
+    detach_conversation_actor(state, conversation_id)
+}
+
+/// Mark a conversation as archived or not
+pub fn set_conversation_archived(
+    state: &mut InterfaceState,
+    conversation_id: &str,
+    archived: bool,
+    timestamp: u64,
+) -> bool {
+    if let Some(metadata) = state.conversation_metadata.get_mut(conversation_id) {
+        metadata.archived = archived;
+        metadata.updated_at = timestamp;
+        true
+    } else {
+        false
+    }
+}
+
+/// Get conversation metadata, optionally leaving out archived conversations
+pub fn list_conversations(
+    state: &InterfaceState,
+    include_archived: bool,
+) -> HashMap<String, ConversationMetadata> {
+    state
+        .conversation_metadata
+        .iter()
+        .filter(|(_, meta)| include_archived || !meta.archived)
+        .map(|(id, meta)| (id.clone(), meta.clone()))
+        .collect()
+}
+
+/// Get the connections that currently have a conversation open
+pub fn get_connections_for_conversation(state: &InterfaceState, conversation_id: &str) -> Vec<u64> {
+    state
+        .connections
+        .values()
+        .filter(|conn| conn.active_conversation_id.as_deref() == Some(conversation_id))
+        .map(|conn| conn.connection_id)
+        .collect()
+}
+
+/// Record which conversation and message a conversation was forked from
+pub fn set_fork_origin(
+    state: &mut InterfaceState,
+    conversation_id: &str,
+    origin: ForkOrigin,
+) -> bool {
+    if let Some(metadata) = state.conversation_metadata.get_mut(conversation_id) {
+        metadata.forked_from = Some(origin);
+        true
+    } else {
+        false
+    }
+}
+
+/// Remember a head of a conversation. Returns true if it wasn't known yet.
+pub fn record_head(state: &mut InterfaceState, conversation_id: &str, head: &str) -> bool {
+    let heads = state
+        .known_heads
+        .entry(conversation_id.to_string())
+        .or_default();
+
+    if heads.iter().any(|h| h == head) {
+        false
+    } else {
+        if heads.len() >= MAX_KNOWN_HEADS {
+            heads.remove(0);
+        }
+        heads.push(head.to_string());
+        true
+    }
+}
+
+/// Forget heads that have since been built on and are no longer branch tips.
+/// Returns true if any head was dropped.
+pub fn prune_known_heads(
+    state: &mut InterfaceState,
+    conversation_id: &str,
+    leaves: &[String],
+) -> bool {
+    match state.known_heads.get_mut(conversation_id) {
+        Some(heads) => {
+            let before = heads.len();
+            heads.retain(|head| leaves.contains(head));
+            heads.len() != before
+        }
+        None => false,
+    }
+}
+
+/// Remember that a reply was cut short. Returns true if it wasn't known yet.
+pub fn mark_truncated(state: &mut InterfaceState, conversation_id: &str, message_id: &str) -> bool {
+    let ids = state
+        .truncated_messages
+        .entry(conversation_id.to_string())
+        .or_default();
+
+    if ids.iter().any(|id| id == message_id) {
+        false
+    } else {
+        ids.push(message_id.to_string());
+        true
+    }
+}
+
+/// Set the truncated marker on messages of a conversation that were cut short
+pub fn flag_truncated(state: &InterfaceState, conversation_id: &str, messages: &mut [ChatMessage]) {
+    let ids = match state.truncated_messages.get(conversation_id) {
+        Some(ids) => ids,
+        None => return,
+    };
+
+    for message in messages {
+        if let Some(id) = &message.id {
+            message.truncated = ids.contains(id);
+        }
+    }
+}
+
+/// Get every head a conversation is known to have had
+pub fn get_known_heads(state: &InterfaceState, conversation_id: &str) -> Vec<String> {
+    state
+        .known_heads
+        .get(conversation_id)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Create or replace a settings preset
+pub fn save_preset(state: &mut InterfaceState, name: String, settings: ConversationSettings) {
+    state.presets.presets.insert(name, settings);
+}
+
+/// Delete a settings preset, clearing the default if it was the default.
+/// Returns false if there is no such preset.
+pub fn remove_preset(state: &mut InterfaceState, name: &str) -> bool {
+    if state.presets.presets.remove(name).is_none() {
+        return false;
+    }
+    if state.presets.default_preset.as_deref() == Some(name) {
+        state.presets.default_preset = None;
+    }
+    true
+}
+
+/// Choose the default preset. Returns false if the named preset doesn't exist.
+pub fn set_default_preset(state: &mut InterfaceState, name: Option<String>) -> bool {
+    if let Some(name) = &name {
+        if !state.presets.presets.contains_key(name) {
+            return false;
+        }
+    }
+    state.presets.default_preset = name;
+    true
+}
+
+/// Settings of the named preset, or of the default preset when no name is given
+pub fn resolve_preset(
+    state: &InterfaceState,
+    name: Option<&str>,
+) -> Result<Option<ConversationSettings>, String> {
+    match name {
+        Some(name) => match state.presets.presets.get(name) {
+            Some(settings) => Ok(Some(settings.clone())),
+            None => Err(format!("Preset {} not found", name)),
+        },
+        None => Ok(state
+            .presets
+            .default_preset
+            .as_ref()
+            .and_then(|name| state.presets.presets.get(name))
+            .cloned()),
+    }
+}
+
 /// Update conversation title
 pub fn update_conversation_title(
     state: &mut InterfaceState,
     conversation_id: &str,
     new_title: String,
+    source: TitleSource,
 ) -> Result<(), String> {
     // Check if the conversation exists
     if let Some(metadata) = state.conversation_metadata.get_mut(conversation_id) {
         // Update the title
         metadata.title = new_title;
+        metadata.title_source = source;
         metadata.updated_at = crate::bindings::ntwk::theater::timing::now();
-        
+
         // Store the updated state
         store_state(state)?;
-        
+
         Ok(())
     } else {
         Err(format!("Conversation {} not found", conversation_id))
     }
 }
 
-/// Update conversation metadata
+/// Update the message count and last message preview of a conversation
 pub fn update_conversation_metadata(
     state: &mut InterfaceState,
     conversation_id: &str,
-    message_count: Option<u32>,
-    last_message: Option<String>,
+    message_count: u32,
+    last_message: Option<&Message>,
     timestamp: u64,
 ) -> bool {
     if let Some(metadata) = state.conversation_metadata.get_mut(conversation_id) {
-        if let Some(count) = message_count {
-            metadata.message_count = count;
-        }
-
-        if let Some(message) = last_message {
-            // Truncate message for preview
-            let preview = if message.len() > 50 {
-                format!("{}...", &message[0..47])
-            } else {
-                message
-            };
-            metadata.last_message_preview = Some(preview);
-        }
-
+        metadata.message_count = message_count;
+        metadata.last_message_preview =
+            last_message.map(|message| preview_text(message, LIST_PREVIEW_CHARS));
         metadata.updated_at = timestamp;
         true
     } else {
@@ -251,6 +1164,18 @@ pub fn get_actor_id_for_conversation(
     state.conversation_actors.get(conversation_id).cloned()
 }
 
+/// Get the subscription channel for a conversation, if one is open
+pub fn get_subscription_for_conversation(
+    state: &InterfaceState,
+    conversation_id: &str,
+) -> Option<String> {
+    state
+        .subscriptions
+        .iter()
+        .find(|(_, conv_id)| conv_id.as_str() == conversation_id)
+        .map(|(channel_id, _)| channel_id.clone())
+}
+
 /// Get the active conversation for a connection
 pub fn get_active_conversation(state: &InterfaceState, connection_id: u64) -> Option<String> {
     state
@@ -258,3 +1183,602 @@ pub fn get_active_conversation(state: &InterfaceState, connection_id: u64) -> Op
         .get(&connection_id)
         .and_then(|conn| conn.active_conversation_id.clone())
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn test_state() -> InterfaceState {
+        InterfaceState {
+            connections: HashMap::new(),
+            conversation_actors: HashMap::new(),
+            conversation_metadata: HashMap::new(),
+            server_config: default_server_config(),
+            store_id: "store".to_string(),
+            server_id: None,
+            subscriptions: HashMap::new(),
+            active_streams: HashMap::new(),
+            known_heads: HashMap::new(),
+            truncated_messages: HashMap::new(),
+            presets: SettingsPresets::default(),
+            models: Vec::new(),
+            chat_state_config: ChatStateConfig {
+                manifest_path: "chat-state.toml".to_string(),
+                init: None,
+                provider_manifests: HashMap::new(),
+            },
+            peak_connections: 0,
+            last_idle_sweep: 0,
+            sweep_timer: None,
+            sessions: HashMap::new(),
+            session_secret: "secret".to_string(),
+            titling_jobs: HashMap::new(),
+            pending_titles: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn start_stream(
+        state: &mut InterfaceState,
+        conversation_id: &str,
+        connection_id: u64,
+    ) {
+        state.active_streams.insert(
+            conversation_id.to_string(),
+            StreamInfo {
+                connection_id,
+                request_id: None,
+                partial: String::new(),
+                base_head: None,
+                restore_head: None,
+                deadline: STREAM_TIMEOUT_MS,
+            },
+        );
+    }
+
+    #[test]
+    fn rehome_streams_moves_stream_to_another_viewer() {
+        let mut state = test_state();
+        add_connection(&mut state, 1, MIN_PROTOCOL_VERSION, 0);
+        add_connection(&mut state, 2, MIN_PROTOCOL_VERSION, 0);
+        set_active_conversation(&mut state, 1, "conv".to_string(), 0);
+        set_active_conversation(&mut state, 2, "conv".to_string(), 0);
+        start_stream(&mut state, "conv", 1);
+
+        rehome_streams(&mut state, 1);
+
+        assert_eq!(state.active_streams["conv"].connection_id, 2);
+    }
+
+    #[test]
+    fn rehome_streams_keeps_owner_without_other_viewers() {
+        let mut state = test_state();
+        add_connection(&mut state, 1, MIN_PROTOCOL_VERSION, 0);
+        add_connection(&mut state, 2, MIN_PROTOCOL_VERSION, 0);
+        set_active_conversation(&mut state, 1, "conv".to_string(), 0);
+        set_active_conversation(&mut state, 2, "other".to_string(), 0);
+        start_stream(&mut state, "conv", 1);
+        start_stream(&mut state, "other", 2);
+
+        rehome_streams(&mut state, 1);
+
+        assert_eq!(state.active_streams["conv"].connection_id, 1);
+        assert_eq!(state.active_streams["other"].connection_id, 2);
+    }
+
+    #[test]
+    fn release_orphaned_streams_keeps_resumable_streams() {
+        let mut state = test_state();
+        add_connection(&mut state, 1, MIN_PROTOCOL_VERSION, 0);
+        add_connection(&mut state, 2, MIN_PROTOCOL_VERSION, 0);
+        start_session(&mut state, "session".to_string(), 2);
+        suspend_session(&mut state, 2, 0);
+        remove_connection(&mut state, 2);
+        start_stream(&mut state, "live", 1);
+        start_stream(&mut state, "away", 2);
+        start_stream(&mut state, "gone", 3);
+        for conversation_id in ["live", "away", "gone", "idle"] {
+            state.subscriptions.insert(
+                format!("{}-channel", conversation_id),
+                conversation_id.to_string(),
+            );
+        }
+
+        let mut released = release_orphaned_streams(&mut state);
+        released.sort();
+
+        assert_eq!(released, vec!["gone-channel", "idle-channel"]);
+        assert!(state.active_streams.contains_key("live"));
+        assert!(state.active_streams.contains_key("away"));
+        assert!(!state.active_streams.contains_key("gone"));
+    }
+
+    pub(crate) fn start_offline_session(
+        state: &mut InterfaceState,
+        session_id: &str,
+        connection_id: u64,
+        timestamp: u64,
+    ) {
+        add_connection(state, connection_id, MIN_PROTOCOL_VERSION, timestamp);
+        start_session(state, session_id.to_string(), connection_id);
+        suspend_session(state, connection_id, timestamp);
+        remove_connection(state, connection_id);
+    }
+
+    #[test]
+    fn resume_session_refuses_open_connection() {
+        let mut state = test_state();
+        add_connection(&mut state, 1, MIN_PROTOCOL_VERSION, 0);
+        start_session(&mut state, "session".to_string(), 1);
+        add_connection(&mut state, 2, MIN_PROTOCOL_VERSION, 0);
+
+        assert!(resume_session(&mut state, "session", 2).is_none());
+        assert_eq!(state.sessions["session"].connection_id, 1);
+    }
+
+    #[test]
+    fn resume_session_replays_in_the_new_protocol_version() {
+        let mut state = test_state();
+        start_offline_session(&mut state, "session", 1, 0);
+        let full = crate::protocol::create_messages_response("conv", Vec::new());
+        let delta = ServerMessage::MessagesAppended {
+            conversation_id: "conv".to_string(),
+            head_id: "head".to_string(),
+            messages: Vec::new(),
+        };
+        buffer_versioned_message(&mut state, "session", Some("req"), &full, &delta);
+        add_connection(&mut state, 2, DELTA_REPLIES_VERSION, 0);
+
+        let replayed = resume_session(&mut state, "session", 2).unwrap();
+
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(replayed[0].request_id.as_deref(), Some("req"));
+        assert!(matches!(
+            replayed[0].message,
+            ServerMessage::MessagesAppended { .. }
+        ));
+        assert!(state.sessions["session"].buffered.is_empty());
+        assert_eq!(state.sessions["session"].buffered_bytes, 0);
+    }
+
+    #[test]
+    fn resumed_connection_is_remembered() {
+        let mut state = test_state();
+        start_offline_session(&mut state, "session", 1, 0);
+        add_connection(&mut state, 2, MIN_PROTOCOL_VERSION, 0);
+        add_connection(&mut state, 3, MIN_PROTOCOL_VERSION, 0);
+        start_session(&mut state, "fresh".to_string(), 3);
+
+        resume_session(&mut state, "session", 2).unwrap();
+
+        assert!(is_resumed_connection(&state, 2));
+        assert!(!is_resumed_connection(&state, 3));
+        assert!(!is_resumed_connection(&state, 4));
+    }
+
+    #[test]
+    fn buffer_message_drops_oldest_past_byte_limit() {
+        let mut state = test_state();
+        start_offline_session(&mut state, "session", 1, 0);
+        let text = "x".repeat(MAX_BUFFERED_BYTES / 3);
+        for conversation_id in ["first", "second", "third"] {
+            let message = crate::protocol::create_error_message(conversation_id, &text, "TEST");
+            buffer_message(&mut state, "session", None, &message);
+        }
+
+        let session = &state.sessions["session"];
+        assert_eq!(session.buffered.len(), 2);
+        assert!(session.buffered_bytes <= MAX_BUFFERED_BYTES);
+        assert_eq!(
+            session.buffered_bytes,
+            session.buffered.iter().map(|m| m.size).sum::<usize>()
+        );
+    }
+
+    #[test]
+    fn buffer_message_skips_deltas() {
+        let mut state = test_state();
+        start_offline_session(&mut state, "session", 1, 0);
+        let delta = crate::protocol::create_completion_delta_message("conv", "text");
+
+        buffer_message(&mut state, "session", None, &delta);
+
+        assert!(state.sessions["session"].buffered.is_empty());
+    }
+
+    #[test]
+    fn suspend_session_forgets_longest_gone_past_limit() {
+        let mut state = test_state();
+        for i in 0..=MAX_OFFLINE_SESSIONS as u64 {
+            start_offline_session(&mut state, &format!("s{}", i), i, i);
+        }
+
+        assert_eq!(state.sessions.len(), MAX_OFFLINE_SESSIONS);
+        assert!(!state.sessions.contains_key("s0"));
+        assert!(state.sessions.contains_key("s1"));
+    }
+
+    #[test]
+    fn expire_sessions_keeps_connected_and_recent_sessions() {
+        let mut state = test_state();
+        start_offline_session(&mut state, "old", 1, 0);
+        start_offline_session(&mut state, "recent", 2, SESSION_TTL_MS);
+        add_connection(&mut state, 3, MIN_PROTOCOL_VERSION, 0);
+        start_session(&mut state, "connected".to_string(), 3);
+
+        expire_sessions(&mut state, SESSION_TTL_MS + 1);
+
+        assert!(!state.sessions.contains_key("old"));
+        assert!(state.sessions.contains_key("recent"));
+        assert!(state.sessions.contains_key("connected"));
+    }
+
+    #[test]
+    fn get_expired_streams_returns_only_streams_past_deadline() {
+        let mut state = test_state();
+        start_stream(&mut state, "quiet", 1);
+        start_stream(&mut state, "busy", 2);
+        state.active_streams.get_mut("busy").unwrap().deadline = 2 * STREAM_TIMEOUT_MS;
+
+        assert!(get_expired_streams(&state, STREAM_TIMEOUT_MS).is_empty());
+        assert_eq!(
+            get_expired_streams(&state, STREAM_TIMEOUT_MS + 1),
+            vec!["quiet".to_string()]
+        );
+    }
+
+    #[test]
+    fn record_head_forgets_oldest_past_limit() {
+        let mut state = test_state();
+        for i in 0..MAX_KNOWN_HEADS {
+            assert!(record_head(&mut state, "conv", &format!("m{}", i)));
+        }
+        assert!(!record_head(&mut state, "conv", "m0"));
+
+        assert!(record_head(&mut state, "conv", "new"));
+        let heads = get_known_heads(&state, "conv");
+        assert_eq!(heads.len(), MAX_KNOWN_HEADS);
+        assert_eq!(heads[0], "m1");
+        assert_eq!(heads.last().map(String::as_str), Some("new"));
+    }
+
+    #[test]
+    fn prune_known_heads_keeps_only_leaves() {
+        let mut state = test_state();
+        record_head(&mut state, "conv", "a");
+        record_head(&mut state, "conv", "b");
+
+        assert!(prune_known_heads(&mut state, "conv", &["b".to_string()]));
+        assert_eq!(get_known_heads(&state, "conv"), vec!["b".to_string()]);
+        assert!(!prune_known_heads(&mut state, "conv", &["b".to_string()]));
+    }
+
+    #[test]
+    fn flag_truncated_marks_only_cut_short_replies() {
+        let mut state = test_state();
+        assert!(mark_truncated(&mut state, "conv", "m2"));
+        assert!(!mark_truncated(&mut state, "conv", "m2"));
+
+        let message = |id: &str| ChatMessage {
+            id: Some(id.to_string()),
+            parent_id: None,
+            message: Message {
+                role: "assistant".to_string(),
+                content: Vec::new(),
+            },
+            truncated: false,
+        };
+        let mut messages = vec![message("m1"), message("m2")];
+        flag_truncated(&state, "conv", &mut messages);
+        assert!(!messages[0].truncated);
+        assert!(messages[1].truncated);
+
+        let mut other = vec![message("m2")];
+        flag_truncated(&state, "other", &mut other);
+        assert!(!other[0].truncated);
+    }
+
+    #[test]
+    fn migrate_title_sources_keeps_only_placeholders_default() {
+        let mut state = test_state();
+        for (id, title) in [
+            ("conv-1", DEFAULT_TITLE.to_string()),
+            ("conv-2", "Conversation conv-2".to_string()),
+            ("conv-3", "Fork of Trip planning".to_string()),
+            ("conv-4", "Renamed".to_string()),
+        ] {
+            register_conversation_actor(
+                &mut state,
+                id.to_string(),
+                format!("actor-{}", id),
+                title,
+                TitleSource::Default,
+                None,
+                0,
+            );
+        }
+
+        assert!(migrate_title_sources(&mut state.conversation_metadata));
+        let source = |id: &str| state.conversation_metadata[id].title_source;
+        assert_eq!(source("conv-1"), TitleSource::Default);
+        assert_eq!(source("conv-2"), TitleSource::Default);
+        assert_eq!(source("conv-3"), TitleSource::User);
+        assert_eq!(source("conv-4"), TitleSource::User);
+
+        assert!(!migrate_title_sources(&mut state.conversation_metadata));
+    }
+
+    fn preset(model: &str) -> ConversationSettings {
+        ConversationSettings {
+            model_config: crate::protocol::ModelConfig {
+                model: model.to_string(),
+                provider: "anthropic".to_string(),
+            },
+            temperature: None,
+            max_tokens: 1024,
+            additional_params: None,
+            system_prompt: None,
+            title: "Preset".to_string(),
+            mcp_servers: Vec::new(),
+        }
+    }
+
+    fn preset_model(settings: Option<ConversationSettings>) -> Option<String> {
+        settings.map(|settings| settings.model_config.model)
+    }
+
+    #[test]
+    fn resolve_preset_picks_named_then_default() {
+        let mut state = test_state();
+        save_preset(&mut state, "fast".to_string(), preset("haiku"));
+        save_preset(&mut state, "smart".to_string(), preset("opus"));
+
+        assert_eq!(preset_model(resolve_preset(&state, None).unwrap()), None);
+        assert!(set_default_preset(&mut state, Some("smart".to_string())));
+        assert_eq!(
+            preset_model(resolve_preset(&state, None).unwrap()).as_deref(),
+            Some("opus")
+        );
+        assert_eq!(
+            preset_model(resolve_preset(&state, Some("fast")).unwrap()).as_deref(),
+            Some("haiku")
+        );
+        assert!(resolve_preset(&state, Some("missing")).is_err());
+    }
+
+    #[test]
+    fn save_preset_replaces_existing() {
+        let mut state = test_state();
+        save_preset(&mut state, "fast".to_string(), preset("haiku"));
+        save_preset(&mut state, "fast".to_string(), preset("sonnet"));
+
+        assert_eq!(state.presets.presets.len(), 1);
+        assert_eq!(
+            preset_model(resolve_preset(&state, Some("fast")).unwrap()).as_deref(),
+            Some("sonnet")
+        );
+    }
+
+    #[test]
+    fn set_default_preset_requires_existing_preset() {
+        let mut state = test_state();
+        assert!(!set_default_preset(&mut state, Some("missing".to_string())));
+        assert_eq!(state.presets.default_preset, None);
+
+        save_preset(&mut state, "fast".to_string(), preset("haiku"));
+        assert!(set_default_preset(&mut state, Some("fast".to_string())));
+        assert!(set_default_preset(&mut state, None));
+        assert_eq!(state.presets.default_preset, None);
+    }
+
+    #[test]
+    fn remove_preset_clears_default() {
+        let mut state = test_state();
+        save_preset(&mut state, "fast".to_string(), preset("haiku"));
+        save_preset(&mut state, "smart".to_string(), preset("opus"));
+        set_default_preset(&mut state, Some("fast".to_string()));
+
+        assert!(remove_preset(&mut state, "smart"));
+        assert_eq!(state.presets.default_preset.as_deref(), Some("fast"));
+        assert!(remove_preset(&mut state, "fast"));
+        assert_eq!(state.presets.default_preset, None);
+        assert!(!remove_preset(&mut state, "fast"));
+    }
+
+    fn register(state: &mut InterfaceState, conversation_id: &str) {
+        register_conversation_actor(
+            state,
+            conversation_id.to_string(),
+            format!("{}-actor", conversation_id),
+            DEFAULT_TITLE.to_string(),
+            TitleSource::Default,
+            None,
+            0,
+        );
+    }
+
+    #[test]
+    fn remove_conversation_forgets_it_everywhere() {
+        let mut state = test_state();
+        register(&mut state, "conv");
+        register(&mut state, "other");
+        record_head(&mut state, "conv", "head");
+        mark_truncated(&mut state, "conv", "head");
+        start_stream(&mut state, "conv", 1);
+        state
+            .subscriptions
+            .insert("channel".to_string(), "conv".to_string());
+        add_connection(&mut state, 1, MIN_PROTOCOL_VERSION, 0);
+        set_active_conversation(&mut state, 1, "conv".to_string(), 0);
+        start_offline_session(&mut state, "away", 2, 0);
+        state
+            .sessions
+            .get_mut("away")
+            .unwrap()
+            .active_conversation_id = Some("conv".to_string());
+
+        assert_eq!(
+            remove_conversation(&mut state, "conv").as_deref(),
+            Some("conv-actor")
+        );
+
+        assert!(!state.conversation_metadata.contains_key("conv"));
+        assert!(!state.conversation_actors.contains_key("conv"));
+        assert!(!state.known_heads.contains_key("conv"));
+        assert!(!state.truncated_messages.contains_key("conv"));
+        assert!(!state.active_streams.contains_key("conv"));
+        assert!(state.subscriptions.is_empty());
+        assert_eq!(get_active_conversation(&state, 1), None);
+        assert_eq!(state.sessions["away"].active_conversation_id, None);
+        assert!(state.conversation_actors.contains_key("other"));
+        assert_eq!(remove_conversation(&mut state, "conv"), None);
+    }
+
+    #[test]
+    fn archived_conversations_are_listed_only_on_request() {
+        let mut state = test_state();
+        register(&mut state, "old");
+        register(&mut state, "new");
+
+        assert!(set_conversation_archived(&mut state, "old", true, 5));
+        assert!(!set_conversation_archived(&mut state, "missing", true, 5));
+        assert_eq!(state.conversation_metadata["old"].updated_at, 5);
+
+        let listed = list_conversations(&state, false);
+        assert_eq!(listed.len(), 1);
+        assert!(listed.contains_key("new"));
+        assert_eq!(list_conversations(&state, true).len(), 2);
+
+        assert!(set_conversation_archived(&mut state, "old", false, 6));
+        assert_eq!(list_conversations(&state, false).len(), 2);
+    }
+
+    #[test]
+    fn detach_conversation_actor_keeps_metadata() {
+        let mut state = test_state();
+        register(&mut state, "conv");
+        add_connection(&mut state, 1, MIN_PROTOCOL_VERSION, 0);
+        set_active_conversation(&mut state, 1, "conv".to_string(), 0);
+
+        assert_eq!(
+            detach_conversation_actor(&mut state, "conv").as_deref(),
+            Some("conv-actor")
+        );
+
+        assert!(state.conversation_metadata.contains_key("conv"));
+        assert_eq!(get_actor_id_for_conversation(&state, "conv"), None);
+        assert_eq!(get_active_conversation(&state, 1), None);
+    }
+
+    #[test]
+    fn set_fork_origin_records_source() {
+        let mut state = test_state();
+        register(&mut state, "fork");
+        let origin = ForkOrigin {
+            conversation_id: "conv".to_string(),
+            message_id: "msg".to_string(),
+        };
+
+        assert!(set_fork_origin(&mut state, "fork", origin.clone()));
+        assert!(!set_fork_origin(&mut state, "missing", origin));
+
+        let value = serde_json::to_value(&state.conversation_metadata["fork"]).unwrap();
+        assert_eq!(
+            value["forked_from"],
+            serde_json::json!({"conversation_id": "conv", "message_id": "msg"})
+        );
+    }
+
+    #[test]
+    fn metadata_stored_before_forks_parses() {
+        let metadata: ConversationMetadata = serde_json::from_str(
+            r#"{"id": "conv", "title": "Old", "created_at": 1, "updated_at": 2,
+                "message_count": 3, "last_message_preview": null}"#,
+        )
+        .unwrap();
+
+        assert!(metadata.forked_from.is_none());
+        assert!(!metadata.archived);
+    }
+
+    #[test]
+    fn update_conversation_metadata_counts_and_previews() {
+        let mut state = test_state();
+        register(&mut state, "conv");
+        let last = Message {
+            role: "assistant".to_string(),
+            content: vec![genai_types::messages::MessageContent::Text {
+                text: "x".repeat(LIST_PREVIEW_CHARS * 2),
+            }],
+        };
+
+        assert!(update_conversation_metadata(
+            &mut state,
+            "conv",
+            4,
+            Some(&last),
+            9
+        ));
+
+        let metadata = &state.conversation_metadata["conv"];
+        assert_eq!(metadata.message_count, 4);
+        assert_eq!(metadata.updated_at, 9);
+        let preview = metadata.last_message_preview.as_deref().unwrap();
+        assert_eq!(preview.chars().count(), LIST_PREVIEW_CHARS);
+        assert!(preview.ends_with("..."));
+
+        assert!(update_conversation_metadata(
+            &mut state, "conv", 0, None, 10
+        ));
+        assert_eq!(
+            state.conversation_metadata["conv"].last_message_preview,
+            None
+        );
+        assert!(!update_conversation_metadata(
+            &mut state, "missing", 1, None, 10
+        ));
+    }
+
+    #[test]
+    fn server_config_fields_default() {
+        let config: ServerConfig = serde_json::from_str(r#"{"port": 9000}"#).unwrap();
+        let defaults = default_server_config();
+
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.host, defaults.host);
+        assert_eq!(config.max_connections, defaults.max_connections);
+        assert_eq!(config.idle_timeout_ms, defaults.idle_timeout_ms);
+        assert!(config.tls.is_none());
+        assert_eq!(config.base_path, "");
+    }
+
+    #[test]
+    fn at_connection_limit_counts_open_connections() {
+        let mut state = test_state();
+        state.server_config.max_connections = 2;
+
+        add_connection(&mut state, 1, MIN_PROTOCOL_VERSION, 0);
+        assert!(!at_connection_limit(&state));
+        add_connection(&mut state, 2, MIN_PROTOCOL_VERSION, 0);
+        assert!(at_connection_limit(&state));
+        assert_eq!(state.peak_connections, 2);
+
+        remove_connection(&mut state, 1);
+        assert!(!at_connection_limit(&state));
+        assert_eq!(state.peak_connections, 2);
+    }
+
+    #[test]
+    fn get_idle_connections_uses_last_activity() {
+        let mut state = test_state();
+        state.server_config.idle_timeout_ms = 100;
+        add_connection(&mut state, 1, MIN_PROTOCOL_VERSION, 0);
+        add_connection(&mut state, 2, MIN_PROTOCOL_VERSION, 0);
+        touch_connection(&mut state, 2, 150);
+
+        assert!(get_idle_connections(&state, 100).is_empty());
+        assert_eq!(get_idle_connections(&state, 200), vec![1]);
+
+        state.server_config.idle_timeout_ms = 0;
+        assert!(get_idle_connections(&state, 10_000).is_empty());
+    }
+}
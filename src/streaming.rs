@@ -0,0 +1,521 @@
+use crate::bindings::ntwk::theater::message_server_host::{open_channel, send};
+use crate::bindings::ntwk::theater::timing::now;
+use crate::chain::{get_head, get_history, messages_after};
+use crate::protocol::{
+    create_completion_delta_message, create_completion_done_message, create_error_message,
+    ChatStateRequest, ChatStateResponse, ChatStateUpdate, ServerMessage,
+};
+use crate::state::{
+    flag_truncated, get_expired_streams, get_subscription_for_conversation, mark_truncated,
+    record_head, store_branches, store_truncated, InterfaceState, StreamInfo, STREAM_TIMEOUT_MS,
+};
+use crate::{
+    broadcast_by_version, broadcast_to_conversation, deliver_by_version, deliver_to_connection,
+    forward_to_chat_state, log, refresh_conversation_metadata, refresh_metadata_from_history,
+    restore_head,
+};
+use genai_types::messages::MessageContent;
+use genai_types::Message;
+
+/// Make sure we hold a subscription channel to the chat-state actor of a conversation
+pub fn ensure_subscription(
+    state: &mut InterfaceState,
+    conversation_id: &str,
+    actor_id: &str,
+) -> Result<String, String> {
+    if let Some(channel_id) = get_subscription_for_conversation(state, conversation_id) {
+        return Ok(channel_id);
+    }
+
+    let subscribe = ChatStateRequest::Subscribe {
+        sub_id: conversation_id.to_string(),
+    };
+    let subscribe_bytes = serde_json::to_vec(&subscribe)
+        .map_err(|e| format!("Failed to serialize subscribe request: {}", e))?;
+
+    let channel_id = open_channel(actor_id, &subscribe_bytes)
+        .map_err(|e| format!("Failed to subscribe to chat-state actor: {}", e))?;
+    log(&format!(
+        "Opened subscription channel {} for conversation {}",
+        channel_id, conversation_id
+    ));
+
+    state
+        .subscriptions
+        .insert(channel_id.clone(), conversation_id.to_string());
+
+    Ok(channel_id)
+}
+
+/// The error to answer with if a completion is running for a conversation. Nothing
+/// else may change its chain until the completion is done.
+pub fn generation_in_progress(
+    state: &InterfaceState,
+    conversation_id: &str,
+) -> Option<ServerMessage> {
+    if state.active_streams.contains_key(conversation_id) {
+        Some(create_error_message(
+            conversation_id,
+            "A completion is already in progress for this conversation",
+            "GENERATION_IN_PROGRESS",
+        ))
+    } else {
+        None
+    }
+}
+
+/// Give up on the streamed completions that produced nothing for STREAM_TIMEOUT_MS,
+/// telling the chat-state actors to stop and the waiting connections why
+pub fn expire_streams(state: &mut InterfaceState, timestamp: u64) {
+    for conversation_id in get_expired_streams(state, timestamp) {
+        log(&format!(
+            "Streamed completion for {} timed out",
+            conversation_id
+        ));
+        if let Some(actor_id) = state.conversation_actors.get(&conversation_id) {
+            let cancel = serde_json::to_vec(&ChatStateRequest::CancelCompletion);
+            if let Err(e) = cancel
+                .map_err(|e| e.to_string())
+                .and_then(|c| send(actor_id, &c))
+            {
+                log(&format!(
+                    "Failed to cancel completion of {}: {}",
+                    conversation_id, e
+                ));
+            }
+        }
+        let error_msg = create_error_message(
+            &conversation_id,
+            "The completion produced no output for too long and was stopped",
+            "GENERATION_TIMEOUT",
+        );
+        fail_stream(state, &conversation_id, &error_msg);
+    }
+}
+
+/// Add a message to a conversation and start a completion without waiting for it.
+/// Progress is delivered to the connection through `handle_chat_state_update`.
+pub fn start_streamed_completion(
+    state: &mut InterfaceState,
+    connection_id: u64,
+    request_id: Option<&str>,
+    conversation_id: &str,
+    actor_id: &str,
+    message: Message,
+) -> Result<ServerMessage, String> {
+    if let Some(busy_msg) = generation_in_progress(state, conversation_id) {
+        return Ok(busy_msg);
+    }
+
+    ensure_subscription(state, conversation_id, actor_id)?;
+
+    // Remember where the chain ended so only the new messages are sent back
+    let base_head = match get_head(actor_id) {
+        Ok(head) => head,
+        Err(e) => return Ok(create_error_message(conversation_id, &e, "HEAD_ERROR")),
+    };
+
+    match forward_to_chat_state(actor_id, &ChatStateRequest::AddMessage { message })? {
+        ChatStateResponse::Success => {}
+        ChatStateResponse::Error { error } => {
+            return Ok(create_error_message(
+                conversation_id,
+                &format!("Error from chat-state actor: {:?}", error),
+                "CHAT_STATE_ERROR",
+            ));
+        }
+        _ => {
+            return Ok(create_error_message(
+                conversation_id,
+                "Unexpected response from chat-state actor",
+                "INTERNAL_ERROR",
+            ));
+        }
+    }
+
+    // Fire and forget, the chat-state actor reports back over the subscription channel
+    let completion_bytes = serde_json::to_vec(&ChatStateRequest::GenerateCompletion)
+        .map_err(|e| format!("Failed to serialize message: {}", e))?;
+    send(actor_id, &completion_bytes)
+        .map_err(|e| format!("Failed to send request to chat-state actor: {}", e))?;
+
+    state.active_streams.insert(
+        conversation_id.to_string(),
+        StreamInfo {
+            connection_id,
+            request_id: request_id.map(String::from),
+            partial: String::new(),
+            base_head,
+            restore_head: None,
+            deadline: now() + STREAM_TIMEOUT_MS,
+        },
+    );
+
+    Ok(ServerMessage::CompletionStarted {
+        conversation_id: conversation_id.to_string(),
+    })
+}
+
+/// Handle an update pushed by a chat-state actor on a subscription channel
+pub fn handle_chat_state_update(
+    state: &mut InterfaceState,
+    channel_id: &str,
+    update: ChatStateUpdate,
+) -> Result<(), String> {
+    let conversation_id = match state.subscriptions.get(channel_id) {
+        Some(id) => id.clone(),
+        None => {
+            log(&format!(
+                "Update on unknown channel {}, ignoring",
+                channel_id
+            ));
+            return Ok(());
+        }
+    };
+
+    // Nobody is waiting on this conversation
+    let (connection_id, request_id) = match state.active_streams.get_mut(&conversation_id) {
+        Some(stream) => {
+            if let ChatStateUpdate::CompletionDelta { text } = &update {
+                stream.partial.push_str(text);
+                stream.deadline = now() + STREAM_TIMEOUT_MS;
+            }
+            (stream.connection_id, stream.request_id.clone())
+        }
+        None => return Ok(()),
+    };
+
+    match update {
+        ChatStateUpdate::CompletionDelta { text } => {
+            let delta_msg = create_completion_delta_message(&conversation_id, &text);
+            send_stream_frame(
+                state,
+                &conversation_id,
+                connection_id,
+                request_id.as_deref(),
+                &delta_msg,
+                None,
+            );
+        }
+        ChatStateUpdate::CompletionDone { head, truncated } => {
+            finish_stream(state, &conversation_id, head, truncated)?;
+        }
+        ChatStateUpdate::Error { error } => {
+            let error_msg = create_error_message(
+                &conversation_id,
+                &format!("Error from chat-state actor: {:?}", error),
+                "CHAT_STATE_ERROR",
+            );
+            fail_stream(state, &conversation_id, &error_msg);
+        }
+    }
+
+    Ok(())
+}
+
+/// End the stream of a conversation that failed and send `error_msg` to the connection
+/// that started it
+fn fail_stream(state: &mut InterfaceState, conversation_id: &str, error_msg: &ServerMessage) {
+    let stream = match state.active_streams.remove(conversation_id) {
+        Some(stream) => stream,
+        None => return,
+    };
+
+    // The user message is already in the chain, unless a failed edit takes it back out
+    if let Some(actor_id) = state.conversation_actors.get(conversation_id).cloned() {
+        if let Some(head) = stream.restore_head {
+            restore_head(&actor_id, conversation_id, head);
+        }
+        refresh_conversation_metadata(state, conversation_id, &actor_id);
+    }
+
+    // The stream is over either way, a dead connection must not keep it alive
+    if let Err(e) = deliver_to_connection(
+        state,
+        stream.connection_id,
+        stream.request_id.as_deref(),
+        error_msg,
+    ) {
+        log(&format!(
+            "Failed to send error to connection {}: {}",
+            stream.connection_id, e
+        ));
+    }
+}
+
+/// Send a frame of a stream to the connection that asked for it, then to the other
+/// viewers of the conversation. `delta` is the form for `DELTA_REPLIES_VERSION` clients,
+/// if it differs. A dead originating connection must not hold up the other viewers,
+/// so failing to reach it is only logged.
+fn send_stream_frame(
+    state: &mut InterfaceState,
+    conversation_id: &str,
+    connection_id: u64,
+    request_id: Option<&str>,
+    full: &ServerMessage,
+    delta: Option<&ServerMessage>,
+) {
+    let delivered = match delta {
+        Some(delta) => deliver_by_version(state, connection_id, request_id, full, delta),
+        None => deliver_to_connection(state, connection_id, request_id, full),
+    };
+    if let Err(e) = delivered {
+        log(&format!(
+            "Failed to send stream update to connection {}: {}",
+            connection_id, e
+        ));
+    }
+
+    match delta {
+        Some(delta) => {
+            broadcast_by_version(state, conversation_id, full, delta, Some(connection_id))
+        }
+        None => broadcast_to_conversation(state, conversation_id, full, Some(connection_id)),
+    }
+}
+
+/// Stop the streamed completion of a conversation. The chat-state actor keeps the
+/// partial output in the chain and the waiting connection gets a truncated `completion_done`.
+pub fn cancel_streamed_completion(
+    state: &mut InterfaceState,
+    conversation_id: &str,
+    actor_id: &str,
+) -> Result<ServerMessage, String> {
+    let partial = match state.active_streams.get(conversation_id) {
+        Some(stream) => stream.partial.clone(),
+        None => {
+            return Ok(create_error_message(
+                conversation_id,
+                "No completion is in progress for this conversation",
+                "NO_ACTIVE_GENERATION",
+            ));
+        }
+    };
+
+    let head = match forward_to_chat_state(actor_id, &ChatStateRequest::CancelCompletion) {
+        Ok(ChatStateResponse::Head { head }) => head,
+        // The chat-state actor dropped the partial output, keep it ourselves
+        Ok(ChatStateResponse::Success) => keep_partial(conversation_id, actor_id, &partial),
+        failed => return Ok(abort_cancel(state, conversation_id, failed)),
+    };
+
+    finish_stream(state, conversation_id, head.clone(), true)?;
+
+    Ok(ServerMessage::GenerationCancelled {
+        conversation_id: conversation_id.to_string(),
+        head_id: head,
+        partial,
+    })
+}
+
+/// End a stream whose cancel the chat-state actor refused or never answered. Nothing
+/// will finish it any more, so it fails and the error answers the cancel as well.
+fn abort_cancel(
+    state: &mut InterfaceState,
+    conversation_id: &str,
+    response: Result<ChatStateResponse, String>,
+) -> ServerMessage {
+    let error_msg = match response {
+        Ok(ChatStateResponse::Error { error }) => create_error_message(
+            conversation_id,
+            &format!("Error from chat-state actor: {:?}", error),
+            "CHAT_STATE_ERROR",
+        ),
+        Ok(_) => create_error_message(
+            conversation_id,
+            "Unexpected response from chat-state actor",
+            "INTERNAL_ERROR",
+        ),
+        Err(e) => create_error_message(conversation_id, &e, "CHAT_STATE_ERROR"),
+    };
+    fail_stream(state, conversation_id, &error_msg);
+    error_msg
+}
+
+/// Add the partial output of a cancelled completion to the chain as an assistant
+/// message, returning the new head. Failures are logged, the cancel still goes through.
+fn keep_partial(conversation_id: &str, actor_id: &str, partial: &str) -> Option<String> {
+    if partial.is_empty() {
+        return None;
+    }
+
+    let message = Message {
+        role: "assistant".to_string(),
+        content: vec![MessageContent::Text {
+            text: partial.to_string(),
+        }],
+    };
+    let kept = match forward_to_chat_state(actor_id, &ChatStateRequest::AddMessage { message }) {
+        Ok(ChatStateResponse::Success) => get_head(actor_id),
+        Ok(response) => Err(format!("Unexpected response: {:?}", response)),
+        Err(e) => Err(e),
+    };
+
+    match kept {
+        Ok(head) => head,
+        Err(e) => {
+            log(&format!(
+                "Failed to keep partial reply of {}: {}",
+                conversation_id, e
+            ));
+            None
+        }
+    }
+}
+
+/// End the stream of a conversation and send the final history to its connection
+/// and everyone else viewing the conversation
+fn finish_stream(
+    state: &mut InterfaceState,
+    conversation_id: &str,
+    head: Option<String>,
+    truncated: bool,
+) -> Result<(), String> {
+    let stream = match state.active_streams.remove(conversation_id) {
+        Some(stream) => stream,
+        None => return Ok(()),
+    };
+    let connection_id = stream.connection_id;
+
+    if let Some(head) = &head {
+        if record_head(state, conversation_id, head) {
+            store_branches(state)?;
+        }
+        if truncated && mark_truncated(state, conversation_id, head) {
+            store_truncated(state)?;
+        }
+    }
+
+    let actor_id = match state.conversation_actors.get(conversation_id) {
+        Some(id) => id.clone(),
+        None => return Ok(()),
+    };
+
+    let history = match get_history(&actor_id) {
+        Ok(history) => history,
+        Err(e) => {
+            let error_msg = create_error_message(conversation_id, &e, "CHAT_STATE_ERROR");
+            if let Err(e) = deliver_to_connection(
+                state,
+                connection_id,
+                stream.request_id.as_deref(),
+                &error_msg,
+            ) {
+                log(&format!(
+                    "Failed to send error to connection {}: {}",
+                    connection_id, e
+                ));
+            }
+            return Ok(());
+        }
+    };
+
+    // Older clients get the whole history, newer ones only the messages this
+    // completion added, with their chain IDs
+    let mut appended = messages_after(&history, stream.base_head.as_deref());
+    flag_truncated(state, conversation_id, &mut appended);
+    let client_messages: Vec<Message> = history.iter().map(|m| m.message.clone()).collect();
+    let full_msg = create_completion_done_message(
+        conversation_id,
+        head.clone(),
+        client_messages,
+        Vec::new(),
+        truncated,
+    );
+    let delta_msg =
+        create_completion_done_message(conversation_id, head, Vec::new(), appended, truncated);
+
+    send_stream_frame(
+        state,
+        conversation_id,
+        connection_id,
+        stream.request_id.as_deref(),
+        &full_msg,
+        Some(&delta_msg),
+    );
+
+    // After the reply went out, so viewers see the reply before the list update
+    refresh_metadata_from_history(state, conversation_id, &history);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ErrorInfo;
+    use crate::state::tests::{start_offline_session, start_stream, test_state};
+
+    #[test]
+    fn abort_cancel_ends_stream_and_tells_its_connection() {
+        let mut state = test_state();
+        start_offline_session(&mut state, "session", 1, 0);
+        start_stream(&mut state, "conv", 1);
+        state.active_streams.get_mut("conv").unwrap().request_id = Some("req".to_string());
+
+        let response = abort_cancel(&mut state, "conv", Err("actor is gone".to_string()));
+
+        assert!(matches!(response, ServerMessage::Error { .. }));
+        assert!(!state.active_streams.contains_key("conv"));
+        let buffered = &state.sessions["session"].buffered;
+        assert_eq!(buffered.len(), 1);
+        assert_eq!(buffered[0].request_id.as_deref(), Some("req"));
+        assert!(matches!(buffered[0].message, ServerMessage::Error { .. }));
+    }
+
+    #[test]
+    fn abort_cancel_ends_stream_on_chat_state_error() {
+        let mut state = test_state();
+        start_offline_session(&mut state, "session", 1, 0);
+        start_stream(&mut state, "conv", 1);
+        let error = ErrorInfo {
+            code: "failed".to_string(),
+            message: "cancel failed".to_string(),
+            details: None,
+        };
+
+        abort_cancel(&mut state, "conv", Ok(ChatStateResponse::Error { error }));
+
+        assert!(state.active_streams.is_empty());
+        assert_eq!(state.sessions["session"].buffered.len(), 1);
+    }
+
+    #[test]
+    fn send_stream_frame_reaches_origin_and_other_viewers() {
+        let mut state = test_state();
+        start_offline_session(&mut state, "origin", 1, 0);
+        start_offline_session(&mut state, "viewer", 2, 0);
+        start_offline_session(&mut state, "elsewhere", 3, 0);
+        for session_id in ["origin", "viewer"] {
+            state
+                .sessions
+                .get_mut(session_id)
+                .unwrap()
+                .active_conversation_id = Some("conv".to_string());
+        }
+        let full = create_completion_done_message("conv", None, Vec::new(), Vec::new(), false);
+
+        send_stream_frame(&mut state, "conv", 1, Some("req"), &full, Some(&full));
+
+        let origin = &state.sessions["origin"].buffered;
+        assert_eq!(origin.len(), 1);
+        assert_eq!(origin[0].request_id.as_deref(), Some("req"));
+        let viewer = &state.sessions["viewer"].buffered;
+        assert_eq!(viewer.len(), 1);
+        assert_eq!(viewer[0].request_id, None);
+        assert!(state.sessions["elsewhere"].buffered.is_empty());
+    }
+
+    #[test]
+    fn generation_in_progress_only_while_streaming() {
+        let mut state = test_state();
+        assert!(generation_in_progress(&state, "conv").is_none());
+
+        start_stream(&mut state, "conv", 1);
+
+        assert!(matches!(
+            generation_in_progress(&state, "conv"),
+            Some(ServerMessage::Error { ref error_code, .. }) if error_code == "GENERATION_IN_PROGRESS"
+        ));
+        assert!(generation_in_progress(&state, "other").is_none());
+    }
+}
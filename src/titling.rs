@@ -0,0 +1,402 @@
+use crate::bindings::ntwk::theater::message_server_host::{close_channel, open_channel, send};
+use crate::bindings::ntwk::theater::supervisor::stop_child;
+use crate::bindings::ntwk::theater::timing::now;
+use crate::chain::{get_message, message_text};
+use crate::protocol::{
+    ChatMessage, ChatStateRequest, ChatStateUpdate, ConversationSettings, TitleSource,
+};
+use crate::state::{InterfaceState, TitlingJob};
+use crate::{
+    apply_generated_title, log, remove_chat_state_labels, seed_chat_state_actor,
+    start_chat_state_actor,
+};
+use genai_types::messages::MessageContent;
+use genai_types::Message;
+
+/// Title of a conversation until one is generated or chosen by the user
+pub const DEFAULT_TITLE: &str = "New conversation";
+
+/// Longest generated title kept, in characters
+const MAX_TITLE_CHARS: usize = 60;
+
+/// Token budget of the titling completion
+const TITLE_MAX_TOKENS: u32 = 32;
+
+/// How long a title may take before it is given up on, in milliseconds
+const TITLE_TIMEOUT_MS: u64 = 2 * 60 * 1000;
+
+/// Instructions for the chat-state actor that writes titles
+const TITLE_PROMPT: &str = "You write titles for chat conversations. Reply with a short title \
+of at most six words describing the conversation you are given. Reply with the title only, \
+without quotes or a trailing full stop.";
+
+/// Whether a conversation is due a generated title: its title is still the placeholder,
+/// the user has asked one thing, and the assistant has finished answering it. Tool use
+/// makes the answer span several assistant messages, with tool results in between.
+pub fn needs_title(
+    state: &InterfaceState,
+    conversation_id: &str,
+    messages: &[ChatMessage],
+) -> bool {
+    let untitled = state
+        .conversation_metadata
+        .get(conversation_id)
+        .map(|meta| meta.title_source == TitleSource::Default)
+        .unwrap_or(false);
+
+    let user_turns = messages
+        .iter()
+        .filter(|m| m.message.role == "user" && !is_tool_result(&m.message))
+        .count();
+    let answered = match messages.last() {
+        Some(last) => {
+            last.message.role == "assistant"
+                && !last
+                    .message
+                    .content
+                    .iter()
+                    .any(|c| matches!(c, MessageContent::ToolUse { .. }))
+        }
+        None => false,
+    };
+
+    untitled && user_turns == 1 && answered
+}
+
+/// Whether a user message only carries tool results
+fn is_tool_result(message: &Message) -> bool {
+    !message.content.is_empty()
+        && message
+            .content
+            .iter()
+            .all(|c| matches!(c, MessageContent::ToolResult { .. }))
+}
+
+/// Conversation ID of the temporary chat-state actor that titles a conversation
+fn titling_id(conversation_id: &str) -> String {
+    format!("{}-title", conversation_id)
+}
+
+/// Start writing a title for `messages` without waiting for it. A temporary chat-state
+/// actor on the conversation's model writes it and reports back over a channel, which
+/// `handle_titling_update` picks up.
+pub fn start_title(
+    state: &mut InterfaceState,
+    conversation_id: &str,
+    mut settings: ConversationSettings,
+    messages: &[ChatMessage],
+) -> Result<(), String> {
+    if state
+        .titling_jobs
+        .values()
+        .any(|job| job.conversation_id == conversation_id)
+    {
+        return Ok(());
+    }
+
+    settings.system_prompt = Some(TITLE_PROMPT.to_string());
+    settings.max_tokens = TITLE_MAX_TOKENS;
+    settings.mcp_servers = Vec::new();
+
+    let transcript = messages
+        .iter()
+        .map(|m| format!("{}: {}", m.message.role, message_text(&m.message)))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let request = Message {
+        role: "user".to_string(),
+        content: vec![MessageContent::Text { text: transcript }],
+    };
+
+    let titling_id = titling_id(conversation_id);
+    let actor_id = start_chat_state_actor(
+        &state.chat_state_config,
+        &titling_id,
+        &state.store_id,
+        Some(&settings.model_config.provider),
+    )?;
+    let channel_id = match request_title(&actor_id, &titling_id, settings, request) {
+        Ok(channel_id) => channel_id,
+        Err(e) => {
+            discard_titling_actor(&state.store_id, &actor_id, conversation_id);
+            return Err(e);
+        }
+    };
+
+    state.titling_jobs.insert(
+        channel_id,
+        TitlingJob {
+            conversation_id: conversation_id.to_string(),
+            actor_id,
+            deadline: now() + TITLE_TIMEOUT_MS,
+        },
+    );
+    Ok(())
+}
+
+/// Seed the titling actor, subscribe to it and start its completion, returning the channel
+fn request_title(
+    actor_id: &str,
+    titling_id: &str,
+    settings: ConversationSettings,
+    request: Message,
+) -> Result<String, String> {
+    seed_chat_state_actor(actor_id, Some(settings), vec![request])?;
+
+    let subscribe = ChatStateRequest::Subscribe {
+        sub_id: titling_id.to_string(),
+    };
+    let subscribe_bytes = serde_json::to_vec(&subscribe)
+        .map_err(|e| format!("Failed to serialize subscribe request: {}", e))?;
+    let channel_id = open_channel(actor_id, &subscribe_bytes)
+        .map_err(|e| format!("Failed to subscribe to titling actor: {}", e))?;
+
+    let completion_bytes = serde_json::to_vec(&ChatStateRequest::GenerateCompletion)
+        .map_err(|e| format!("Failed to serialize message: {}", e))?;
+    send(actor_id, &completion_bytes)
+        .map_err(|e| format!("Failed to send request to titling actor: {}", e))?;
+
+    Ok(channel_id)
+}
+
+/// Handle an update from a titling actor, applying the title once it is written.
+/// Returns false if the channel doesn't belong to a titling actor.
+pub fn handle_titling_update(
+    state: &mut InterfaceState,
+    channel_id: &str,
+    update: ChatStateUpdate,
+) -> bool {
+    let job = match state.titling_jobs.get(channel_id) {
+        Some(job) => job.clone(),
+        None => return false,
+    };
+
+    let title = match update {
+        // The finished reply is read from the chain
+        ChatStateUpdate::CompletionDelta { .. } => return true,
+        ChatStateUpdate::CompletionDone {
+            head: Some(head), ..
+        } => get_message(&job.actor_id, &head).and_then(|reply| {
+            clean_title(&message_text(&reply.message))
+                .ok_or_else(|| "Titling model returned an empty title".to_string())
+        }),
+        ChatStateUpdate::CompletionDone { head: None, .. } => {
+            Err("Titling model returned no reply".to_string())
+        }
+        ChatStateUpdate::Error { error } => {
+            Err(format!("Error generating title: {}", error.message))
+        }
+    };
+    end_titling_job(state, channel_id);
+
+    match title {
+        Ok(title) => apply_generated_title(state, &job.conversation_id, &title),
+        Err(e) => log(&format!("Failed to title {}: {}", job.conversation_id, e)),
+    }
+    true
+}
+
+/// Give up on the titles that took longer than TITLE_TIMEOUT_MS
+pub fn expire_titling_jobs(state: &mut InterfaceState, timestamp: u64) {
+    let expired: Vec<String> = state
+        .titling_jobs
+        .iter()
+        .filter(|(_, job)| timestamp > job.deadline)
+        .map(|(channel_id, _)| channel_id.clone())
+        .collect();
+    for channel_id in expired {
+        if let Some(job) = state.titling_jobs.get(&channel_id) {
+            log(&format!("Titling {} timed out", job.conversation_id));
+        }
+        end_titling_job(state, &channel_id);
+    }
+}
+
+/// Forget a titling job, closing its channel and stopping its actor
+pub fn end_titling_job(state: &mut InterfaceState, channel_id: &str) {
+    let job = match state.titling_jobs.remove(channel_id) {
+        Some(job) => job,
+        None => return,
+    };
+    if let Err(e) = close_channel(channel_id) {
+        log(&format!("Failed to close channel {}: {}", channel_id, e));
+    }
+    discard_titling_actor(&state.store_id, &job.actor_id, &job.conversation_id);
+}
+
+/// Stop a titling actor and remove what it saved in the shared store
+fn discard_titling_actor(store_id: &str, actor_id: &str, conversation_id: &str) {
+    if let Err(e) = stop_child(actor_id) {
+        log(&format!(
+            "Failed to stop titling actor {} for conversation {}: {}",
+            actor_id, conversation_id, e
+        ));
+    }
+    remove_chat_state_labels(store_id, &titling_id(conversation_id));
+}
+
+/// First line of a model reply without heading marks or surrounding quotes and emphasis,
+/// limited to `MAX_TITLE_CHARS`
+fn clean_title(reply: &str) -> Option<String> {
+    let line = reply.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let title = line
+        .trim_start_matches('#')
+        .trim()
+        .trim_matches(|c| c == '"' || c == '\'' || c == '*')
+        .trim_end_matches('.')
+        .trim();
+
+    if title.is_empty() {
+        None
+    } else {
+        Some(title.chars().take(MAX_TITLE_CHARS).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::register_conversation_actor;
+    use crate::state::tests::test_state;
+
+    fn chat_message(role: &str, content: MessageContent) -> ChatMessage {
+        ChatMessage {
+            id: None,
+            parent_id: None,
+            message: Message {
+                role: role.to_string(),
+                content: vec![content],
+            },
+            truncated: false,
+        }
+    }
+
+    fn text(role: &str, text: &str) -> ChatMessage {
+        chat_message(
+            role,
+            MessageContent::Text {
+                text: text.to_string(),
+            },
+        )
+    }
+
+    fn tool_use() -> ChatMessage {
+        chat_message(
+            "assistant",
+            MessageContent::ToolUse {
+                id: "tool".to_string(),
+                name: "search".to_string(),
+                input: serde_json::Value::Null,
+            },
+        )
+    }
+
+    fn tool_result() -> ChatMessage {
+        chat_message(
+            "user",
+            MessageContent::ToolResult {
+                tool_use_id: "tool".to_string(),
+                content: Vec::new(),
+                is_error: None,
+            },
+        )
+    }
+
+    fn untitled_state() -> InterfaceState {
+        let mut state = test_state();
+        register_conversation_actor(
+            &mut state,
+            "conv".to_string(),
+            "actor".to_string(),
+            DEFAULT_TITLE.to_string(),
+            TitleSource::Default,
+            None,
+            0,
+        );
+        state
+    }
+
+    #[test]
+    fn clean_title_strips_quotes_and_markdown() {
+        assert_eq!(
+            clean_title("\"Rust lifetimes\""),
+            Some("Rust lifetimes".to_string())
+        );
+        assert_eq!(
+            clean_title("'Rust lifetimes'"),
+            Some("Rust lifetimes".to_string())
+        );
+        assert_eq!(
+            clean_title("**Rust lifetimes**"),
+            Some("Rust lifetimes".to_string())
+        );
+        assert_eq!(
+            clean_title("Rust lifetimes."),
+            Some("Rust lifetimes".to_string())
+        );
+        assert_eq!(
+            clean_title("## Rust lifetimes"),
+            Some("Rust lifetimes".to_string())
+        );
+    }
+
+    #[test]
+    fn clean_title_takes_first_non_blank_line() {
+        assert_eq!(
+            clean_title("\n  \nRust lifetimes\nSecond line"),
+            Some("Rust lifetimes".to_string())
+        );
+        assert_eq!(clean_title("\n \n"), None);
+        assert_eq!(clean_title("\"\""), None);
+    }
+
+    #[test]
+    fn clean_title_truncates_long_titles() {
+        let title = clean_title(&"é".repeat(MAX_TITLE_CHARS + 10)).unwrap();
+        assert_eq!(title.chars().count(), MAX_TITLE_CHARS);
+    }
+
+    #[test]
+    fn needs_title_after_first_exchange() {
+        let state = untitled_state();
+        let messages = vec![text("user", "hello"), text("assistant", "hi")];
+        assert!(needs_title(&state, "conv", &messages));
+        assert!(!needs_title(&state, "conv", &messages[..1]));
+        assert!(!needs_title(&state, "other", &messages));
+    }
+
+    #[test]
+    fn needs_title_after_tool_use_exchange() {
+        let state = untitled_state();
+        let mut messages = vec![
+            text("user", "find it"),
+            tool_use(),
+            tool_result(),
+            tool_use(),
+            tool_result(),
+        ];
+        assert!(!needs_title(&state, "conv", &messages));
+
+        messages.push(text("assistant", "found it"));
+        assert!(needs_title(&state, "conv", &messages));
+    }
+
+    #[test]
+    fn needs_title_skips_later_exchanges_and_titled_conversations() {
+        let mut state = untitled_state();
+        let messages = vec![
+            text("user", "hello"),
+            text("assistant", "hi"),
+            text("user", "again"),
+            text("assistant", "hi again"),
+        ];
+        assert!(!needs_title(&state, "conv", &messages));
+
+        state
+            .conversation_metadata
+            .get_mut("conv")
+            .unwrap()
+            .title_source = TitleSource::User;
+        assert!(!needs_title(&state, "conv", &messages[..2]));
+    }
+}
@@ -0,0 +1,231 @@
+use crate::models::{find_model, is_known_provider, ModelInfo};
+use crate::protocol::{ConversationSettings, FieldError};
+
+/// Check settings against the model catalog before they reach a chat-state actor.
+/// Returns every problem found, an empty list means the settings are valid.
+pub fn validate_settings(settings: &ConversationSettings, models: &[ModelInfo]) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+
+    let provider = &settings.model_config.provider;
+    let model_id = &settings.model_config.model;
+    let model = find_model(models, provider, model_id);
+    if !is_known_provider(models, provider) {
+        errors.push(field_error(
+            "model_config.provider",
+            &format!("Unknown provider {:?}", provider),
+        ));
+    } else if model.is_none() {
+        errors.push(field_error(
+            "model_config.model",
+            &format!("Unknown model {:?} for provider {}", model_id, provider),
+        ));
+    }
+
+    // Each provider has its own range, unknown models are reported above
+    if let (Some(temperature), Some(model)) = (settings.temperature, model) {
+        if !(model.min_temperature..=model.max_temperature).contains(&temperature) {
+            errors.push(field_error(
+                "temperature",
+                &format!(
+                    "{} takes a temperature between {} and {}",
+                    model.display_name, model.min_temperature, model.max_temperature
+                ),
+            ));
+        }
+    }
+
+    if settings.max_tokens == 0 {
+        errors.push(field_error("max_tokens", "Must be greater than 0"));
+    } else if let Some(model) = model {
+        if settings.max_tokens > model.max_output_tokens {
+            errors.push(field_error(
+                "max_tokens",
+                &format!(
+                    "{} allows at most {} output tokens",
+                    model.display_name, model.max_output_tokens
+                ),
+            ));
+        }
+    }
+
+    if settings.title.trim().is_empty() {
+        errors.push(field_error("title", "Must not be empty"));
+    }
+
+    if let Some(model) = model {
+        if !model.supports_tools && !settings.mcp_servers.is_empty() {
+            errors.push(field_error(
+                "mcp_servers",
+                &format!("{} does not support tools", model.display_name),
+            ));
+        }
+    }
+
+    for (i, server) in settings.mcp_servers.iter().enumerate() {
+        if server.config.command.trim().is_empty() {
+            errors.push(field_error(
+                &format!("mcp_servers[{}].config.command", i),
+                "Must not be empty",
+            ));
+        }
+        if server.config.args.iter().any(|arg| arg.contains('\0')) {
+            errors.push(field_error(
+                &format!("mcp_servers[{}].config.args", i),
+                "Arguments must not contain NUL characters",
+            ));
+        }
+        if server
+            .actor_id
+            .as_deref()
+            .is_some_and(|id| id.trim().is_empty())
+        {
+            errors.push(field_error(
+                &format!("mcp_servers[{}].actor_id", i),
+                "Must not be empty when given",
+            ));
+        }
+    }
+
+    errors
+}
+
+fn field_error(field: &str, message: &str) -> FieldError {
+    FieldError {
+        field: field.to_string(),
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::builtin_models;
+    use crate::protocol::{McpConfig, McpServer, ModelConfig};
+
+    fn settings(provider: &str, model: &str) -> ConversationSettings {
+        ConversationSettings {
+            model_config: ModelConfig {
+                model: model.to_string(),
+                provider: provider.to_string(),
+            },
+            temperature: Some(0.7),
+            max_tokens: 1024,
+            additional_params: None,
+            system_prompt: None,
+            title: "Title".to_string(),
+            mcp_servers: Vec::new(),
+        }
+    }
+
+    fn fields(errors: &[FieldError]) -> Vec<&str> {
+        errors.iter().map(|e| e.field.as_str()).collect()
+    }
+
+    fn openai_model() -> ModelInfo {
+        ModelInfo {
+            provider: "openai".to_string(),
+            id: "gpt-4o".to_string(),
+            display_name: "GPT-4o".to_string(),
+            max_output_tokens: 16384,
+            context_window: 128000,
+            supports_tools: false,
+            supports_images: true,
+            min_temperature: 0.0,
+            max_temperature: 2.0,
+        }
+    }
+
+    #[test]
+    fn accepts_valid_settings() {
+        let errors = validate_settings(
+            &settings("anthropic", "claude-3-5-haiku-20241022"),
+            &builtin_models(),
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn rejects_unknown_provider_and_model() {
+        let models = builtin_models();
+        let errors = validate_settings(&settings("nobody", "x"), &models);
+        assert_eq!(fields(&errors), vec!["model_config.provider"]);
+
+        let errors = validate_settings(&settings("anthropic", "x"), &models);
+        assert_eq!(fields(&errors), vec!["model_config.model"]);
+    }
+
+    #[test]
+    fn temperature_range_comes_from_the_model() {
+        let models = vec![openai_model()];
+        let mut openai = settings("openai", "gpt-4o");
+        openai.temperature = Some(1.5);
+        assert!(validate_settings(&openai, &models).is_empty());
+
+        openai.temperature = Some(2.5);
+        assert_eq!(
+            fields(&validate_settings(&openai, &models)),
+            vec!["temperature"]
+        );
+
+        let mut anthropic = settings("anthropic", "claude-3-5-haiku-20241022");
+        anthropic.temperature = Some(1.5);
+        assert_eq!(
+            fields(&validate_settings(&anthropic, &builtin_models())),
+            vec!["temperature"]
+        );
+    }
+
+    #[test]
+    fn catalog_entries_default_to_unit_temperature_range() {
+        let model: ModelInfo = serde_json::from_str(
+            r#"{"provider": "p", "id": "m", "display_name": "M",
+                "max_output_tokens": 10, "context_window": 100}"#,
+        )
+        .unwrap();
+        assert_eq!(model.min_temperature, 0.0);
+        assert_eq!(model.max_temperature, 1.0);
+    }
+
+    #[test]
+    fn max_tokens_is_bounded_by_the_model() {
+        let models = builtin_models();
+        let mut too_many = settings("anthropic", "claude-3-5-haiku-20241022");
+        too_many.max_tokens = 100_000;
+        assert_eq!(
+            fields(&validate_settings(&too_many, &models)),
+            vec!["max_tokens"]
+        );
+
+        too_many.max_tokens = 0;
+        assert_eq!(
+            fields(&validate_settings(&too_many, &models)),
+            vec!["max_tokens"]
+        );
+    }
+
+    #[test]
+    fn checks_title_and_mcp_servers() {
+        let models = vec![openai_model()];
+        let mut invalid = settings("openai", "gpt-4o");
+        invalid.title = "  ".to_string();
+        invalid.mcp_servers = vec![McpServer {
+            actor_id: Some(" ".to_string()),
+            config: McpConfig {
+                command: "".to_string(),
+                args: vec!["a\0b".to_string()],
+            },
+            tools: None,
+        }];
+
+        assert_eq!(
+            fields(&validate_settings(&invalid, &models)),
+            vec![
+                "title",
+                "mcp_servers",
+                "mcp_servers[0].config.command",
+                "mcp_servers[0].config.args",
+                "mcp_servers[0].actor_id",
+            ]
+        );
+    }
+}